@@ -0,0 +1,21 @@
+-- Vaults became per-mint: key vaults by (owner, mint) and record the mint on
+-- every table that refers to a vault. Rows written before this migration all
+-- belong to the single USDT vault (`routes::vault::USDT_MINT`).
+-- A no-op on databases created from the current schema.sql.
+ALTER TABLE vaults ADD COLUMN IF NOT EXISTS mint TEXT;
+UPDATE vaults SET mint = 'EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v' WHERE mint IS NULL;
+ALTER TABLE vaults ALTER COLUMN mint SET NOT NULL;
+ALTER TABLE vaults DROP CONSTRAINT IF EXISTS vaults_pkey;
+ALTER TABLE vaults ADD PRIMARY KEY (owner, mint);
+
+ALTER TABLE vault_transactions ADD COLUMN IF NOT EXISTS mint TEXT;
+UPDATE vault_transactions SET mint = 'EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v' WHERE mint IS NULL;
+ALTER TABLE vault_transactions ALTER COLUMN mint SET NOT NULL;
+
+ALTER TABLE balance_snapshots ADD COLUMN IF NOT EXISTS vault_mint TEXT;
+UPDATE balance_snapshots SET vault_mint = 'EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v' WHERE vault_mint IS NULL;
+ALTER TABLE balance_snapshots ALTER COLUMN vault_mint SET NOT NULL;
+
+ALTER TABLE reconciliation_logs ADD COLUMN IF NOT EXISTS vault_mint TEXT;
+UPDATE reconciliation_logs SET vault_mint = 'EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v' WHERE vault_mint IS NULL;
+ALTER TABLE reconciliation_logs ALTER COLUMN vault_mint SET NOT NULL;
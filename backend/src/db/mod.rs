@@ -19,10 +19,58 @@ pub async fn init_db() -> PgPool {
             .expect(&format!("Failed to execute: {}", query_str));
     }
 
+    run_migrations(&pool).await;
+
     println!("🗄️ Database initialized");
     pool
 }
 
+/// Upgrades for databases created from an older schema.sql, applied in order
+/// and recorded in `schema_migrations` so each runs once.
+const MIGRATIONS: &[(&str, &str)] = &[
+    ("002_vault_mint", include_str!("../../migrations/002_vault_mint.sql")),
+];
+
+async fn run_migrations(pool: &PgPool) {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            name TEXT PRIMARY KEY,
+            applied_at TIMESTAMPTZ NOT NULL
+        );",
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create schema_migrations");
+
+    for (name, sql) in MIGRATIONS {
+        let applied = sqlx::query("SELECT 1 FROM schema_migrations WHERE name = $1")
+            .bind(name)
+            .fetch_optional(pool)
+            .await
+            .expect("Failed to read schema_migrations")
+            .is_some();
+        if applied {
+            continue;
+        }
+
+        let mut tx = pool.begin().await.expect("Failed to start migration");
+        for query_str in sql.split(';').filter(|s| !s.trim().is_empty()) {
+            sqlx::query(&format!("{};", query_str.trim()))
+                .execute(&mut *tx)
+                .await
+                .unwrap_or_else(|e| panic!("Migration {} failed: {}: {}", name, query_str, e));
+        }
+        sqlx::query("INSERT INTO schema_migrations (name, applied_at) VALUES ($1, NOW())")
+            .bind(name)
+            .execute(&mut *tx)
+            .await
+            .expect("Failed to record migration");
+        tx.commit().await.expect("Failed to commit migration");
+
+        println!("🗄️ Applied migration {}", name);
+    }
+}
+
 pub fn now_ts() -> chrono::DateTime<Utc> {
     Utc::now()
 }
\ No newline at end of file
@@ -1,15 +1,18 @@
 CREATE TABLE IF NOT EXISTS vaults (
-    owner TEXT PRIMARY KEY,
+    owner TEXT NOT NULL,
+    mint TEXT NOT NULL,
     vault_pda TEXT NOT NULL,
     total_balance BIGINT NOT NULL,
     locked_balance BIGINT NOT NULL,
     available_balance BIGINT NOT NULL,
-    last_updated TIMESTAMP WITH TIME ZONE NOT NULL
+    last_updated TIMESTAMP WITH TIME ZONE NOT NULL,
+    PRIMARY KEY (owner, mint)
 );
 
 CREATE TABLE IF NOT EXISTS vault_transactions (
     id SERIAL PRIMARY KEY,
     owner TEXT NOT NULL,
+    mint TEXT NOT NULL,
     tx_type TEXT NOT NULL, -- deposit / withdraw / lock / unlock / transfer
     amount BIGINT NOT NULL,
     signature TEXT,
@@ -20,6 +23,7 @@ CREATE TABLE IF NOT EXISTS vault_transactions (
 CREATE TABLE IF NOT EXISTS balance_snapshots (
     id SERIAL PRIMARY KEY,
     vault_owner TEXT NOT NULL,
+    vault_mint TEXT NOT NULL,
     total_balance BIGINT NOT NULL,
     locked_balance BIGINT NOT NULL,
     available_balance BIGINT NOT NULL,
@@ -29,6 +33,7 @@ CREATE TABLE IF NOT EXISTS balance_snapshots (
 CREATE TABLE IF NOT EXISTS reconciliation_logs (
     id SERIAL PRIMARY KEY,
     vault_owner TEXT NOT NULL,
+    vault_mint TEXT NOT NULL,
     discrepancy TEXT,
     resolved BOOLEAN DEFAULT FALSE,
     logged_at TIMESTAMP WITH TIME ZONE NOT NULL
@@ -1,20 +1,20 @@
 use sqlx::PgPool;
 use chrono::{DateTime, Utc};
+use crate::models::VaultBalances;
 
 pub async fn upsert_vault(
     pool: &PgPool,
     owner: &str,
+    mint: &str,
     vault_pda: &str,
-    total: u64,
-    locked: u64,
-    available: u64,
+    balances: &VaultBalances,
     ts: DateTime<Utc>,
 ) {
     sqlx::query(
     r#"
-    INSERT INTO vaults (owner, vault_pda, total_balance, locked_balance, available_balance, last_updated)
-    VALUES ($1, $2, $3, $4, $5, $6)
-    ON CONFLICT(owner) DO UPDATE SET
+    INSERT INTO vaults (owner, mint, vault_pda, total_balance, locked_balance, available_balance, last_updated)
+    VALUES ($1, $2, $3, $4, $5, $6, $7)
+    ON CONFLICT(owner, mint) DO UPDATE SET
         total_balance = excluded.total_balance,
         locked_balance = excluded.locked_balance,
         available_balance = excluded.available_balance,
@@ -22,10 +22,11 @@ pub async fn upsert_vault(
     "#
 )
 .bind(owner)
+.bind(mint)
 .bind(vault_pda)
-.bind(total as i64)
-.bind(locked as i64)
-.bind(available as i64)
+.bind(balances.total as i64)
+.bind(balances.locked as i64)
+.bind(balances.available as i64)
 .bind(ts)
 .execute(pool)
 .await
@@ -41,7 +41,7 @@ async fn main() -> anyhow::Result<()> {
 
     let app = Router::new()
         .route("/health", get(health))
-        .route("/vault/:owner", get(get_vault))
+        .route("/vault/:owner", get(get_vault)) // ?mint=<collateral mint>
         .route("/vault/:owner/balance", get(get_balance))
         .route("/vault/:owner/transactions", get(get_transactions))
         .route("/tvl", get(get_tvl))
@@ -64,19 +64,21 @@ async fn main() -> anyhow::Result<()> {
 }
 
 async fn reconcile_all_vaults(db: &PgPool) {
-    let owners: Vec<String> = sqlx::query("SELECT owner FROM vaults")
+    let vaults: Vec<(String, String)> = sqlx::query("SELECT owner, mint FROM vaults")
         .fetch_all(db)
         .await
         .unwrap()
         .into_iter()
-        .map(|row| row.get::<String, _>(0))
+        .map(|row| (row.get::<String, _>(0), row.get::<String, _>(1)))
         .collect();
 
-    for owner in owners {
+    for (owner, mint) in vaults {
         let owner_pk = Pubkey::from_str(&owner).unwrap();
-        if let Ok((_, vault)) = crate::solana::fetch_vault(&owner_pk) {
-            let db_row = sqlx::query("SELECT total_balance, locked_balance, available_balance FROM vaults WHERE owner = $1")
+        let mint_pk = Pubkey::from_str(&mint).unwrap();
+        if let Ok((_, vault)) = crate::solana::fetch_vault(&owner_pk, &mint_pk) {
+            let db_row = sqlx::query("SELECT total_balance, locked_balance, available_balance FROM vaults WHERE owner = $1 AND mint = $2")
                 .bind(&owner)
+                .bind(&mint)
                 .fetch_one(db)
                 .await
                 .unwrap();
@@ -86,16 +88,18 @@ async fn reconcile_all_vaults(db: &PgPool) {
             let db_available = db_row.get::<i64, _>(2) as u64;
 
             if vault.total_balance != db_total || vault.locked_balance != db_locked || vault.available_balance != db_available {
-                println!("ALERT: Discrepancy for vault {}: on-chain {} vs DB {}", owner, vault.total_balance, db_total);
-                sqlx::query("INSERT INTO reconciliation_logs (vault_owner, discrepancy, logged_at) VALUES ($1, $2, NOW())")
+                println!("ALERT: Discrepancy for vault {} ({}): on-chain {} vs DB {}", owner, mint, vault.total_balance, db_total);
+                sqlx::query("INSERT INTO reconciliation_logs (vault_owner, vault_mint, discrepancy, logged_at) VALUES ($1, $2, $3, NOW())")
                     .bind(&owner)
+                    .bind(&mint)
                     .bind(format!("Mismatch: on-chain {} vs DB {}", vault.total_balance, db_total))
                     .execute(db)
                     .await
                     .unwrap();
             } else {
-                sqlx::query("INSERT INTO balance_snapshots (vault_owner, total_balance, locked_balance, available_balance, snapshot_at) VALUES ($1, $2, $3, $4, NOW())")
+                sqlx::query("INSERT INTO balance_snapshots (vault_owner, vault_mint, total_balance, locked_balance, available_balance, snapshot_at) VALUES ($1, $2, $3, $4, $5, NOW())")
                     .bind(&owner)
+                    .bind(&mint)
                     .bind(vault.total_balance as i64)
                     .bind(vault.locked_balance as i64)
                     .bind(vault.available_balance as i64)
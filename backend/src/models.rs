@@ -3,6 +3,7 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Serialize, Deserialize)]
 pub struct VaultResponse {
     pub owner: String,
+    pub mint: String,
     pub vault_pda: String,
     pub total_balance: u64,
     pub locked_balance: u64,
@@ -34,9 +35,12 @@ pub struct VaultTxRow {
 
 use borsh::{BorshDeserialize, BorshSerialize};
 
+/// On-chain `CollateralVault`, decoded with `solana::decode_vault`.
+/// Only the leading fields are listed; the version byte follows `bump`.
 #[derive(Debug, BorshDeserialize, BorshSerialize)]
 pub struct CollateralVaultAccount {
     pub owner: [u8; 32],
+    pub mint: [u8; 32],
     pub token_account: [u8; 32],
 
     pub total_balance: u64,
@@ -48,14 +52,80 @@ pub struct CollateralVaultAccount {
 
     pub created_at: i64,
     pub bump: u8,
+
+    /// Layout version (0 = legacy, unversioned account)
+    #[borsh(skip)]
+    pub version: u8,
+}
+
+/// Legacy vault at `["vault", owner]`, from before per-mint vaults. It has no
+/// `mint` and no version byte; `migrate_vault` moves it to the current layout.
+#[derive(Debug, BorshDeserialize, BorshSerialize)]
+pub struct LegacyCollateralVaultAccount {
+    pub owner: [u8; 32],
+    pub token_account: [u8; 32],
+
+    pub total_balance: u64,
+    pub locked_balance: u64,
+    pub available_balance: u64,
+
+    pub total_deposited: u64,
+    pub total_withdrawn: u64,
+
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+/// Vault balances cached in the `vaults` table
+#[derive(Debug, Clone, Copy)]
+pub struct VaultBalances {
+    pub total: u64,
+    pub locked: u64,
+    pub available: u64,
+}
+
+impl From<&CollateralVaultAccount> for VaultBalances {
+    fn from(vault: &CollateralVaultAccount) -> Self {
+        VaultBalances {
+            total: vault.total_balance,
+            locked: vault.locked_balance,
+            available: vault.available_balance,
+        }
+    }
+}
+
+impl From<LegacyCollateralVaultAccount> for CollateralVaultAccount {
+    /// The mint is unknown on legacy accounts and comes back zeroed
+    fn from(legacy: LegacyCollateralVaultAccount) -> Self {
+        CollateralVaultAccount {
+            owner: legacy.owner,
+            mint: [0; 32],
+            token_account: legacy.token_account,
+            total_balance: legacy.total_balance,
+            locked_balance: legacy.locked_balance,
+            available_balance: legacy.available_balance,
+            total_deposited: legacy.total_deposited,
+            total_withdrawn: legacy.total_withdrawn,
+            created_at: legacy.created_at,
+            bump: legacy.bump,
+            version: 0,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct AmountRequest {
     pub owner: String,
+    pub mint: String,
     pub amount: u64,
 }
 
+/// `?mint=<collateral mint>`; axum answers 400 when it is missing
+#[derive(Debug, Deserialize)]
+pub struct MintQuery {
+    pub mint: String,
+}
+
 #[derive(Debug, Serialize)]
 pub struct TxResponse {
     pub transaction_base64: String,
@@ -65,6 +135,7 @@ pub struct TxResponse {
 pub struct TransferRequest {
     pub from: String,
     pub to: String,
+    pub mint: String,
     pub amount: u64,
 }
 
@@ -79,6 +150,7 @@ pub struct IntentResponse {
 #[derive(Debug, Deserialize)]
 pub struct ConfirmRequest {
     pub owner: String,
+    pub mint: String,
     pub event_type: String,
     pub amount: u64,
     pub sig: String,
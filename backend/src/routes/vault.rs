@@ -1,7 +1,7 @@
-use axum::{extract::{Path, State, Json}, Json as AxumJson, response::IntoResponse};
+use axum::{extract::{Path, Query, State, Json}, http::StatusCode, Json as AxumJson, response::IntoResponse};
 use solana_sdk::pubkey::Pubkey;
 use std::str::FromStr;
-use crate::models::{AmountRequest, TransferRequest, TxResponse, ConfirmRequest, VaultResponse, IntentResponse};
+use crate::models::{AmountRequest, TransferRequest, TxResponse, ConfirmRequest, VaultResponse, IntentResponse, MintQuery, VaultBalances};
 use crate::solana::fetch_vault;
 use crate::db::upsert_vault;
 use crate::AppState;
@@ -20,34 +20,39 @@ const USDT_MINT: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"; //exampl
 pub async fn get_vault(
     State(state): State<AppState>,
     Path(owner): Path<String>,
-) -> AxumJson<VaultResponse> {
-    let owner_pk = Pubkey::from_str(&owner).expect("Invalid pubkey");
-    let (vault_pda, vault) = fetch_vault(&owner_pk).expect("Vault fetch failed");
+    Query(query): Query<MintQuery>,
+) -> Result<AxumJson<VaultResponse>, (StatusCode, String)> {
+    let owner_pk = Pubkey::from_str(&owner)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid owner pubkey".to_string()))?;
+    let mint_pk = Pubkey::from_str(&query.mint)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid mint pubkey".to_string()))?;
+    let (vault_pda, vault) = fetch_vault(&owner_pk, &mint_pk).expect("Vault fetch failed");
 
     upsert_vault(
         &state.db,
         &owner,
+        &query.mint,
         &vault_pda.to_string(),
-        vault.total_balance,
-        vault.locked_balance,
-        vault.available_balance,
+        &VaultBalances::from(&vault),
         Utc::now(),
     ).await;
 
-    AxumJson(VaultResponse {
+    Ok(AxumJson(VaultResponse {
         owner,
+        mint: query.mint,
         vault_pda: vault_pda.to_string(),
         total_balance: vault.total_balance,
         locked_balance: vault.locked_balance,
         available_balance: vault.available_balance,
-    })
+    }))
 }
 
 pub async fn get_balance(
     State(state): State<AppState>,
     Path(owner): Path<String>,
-) -> AxumJson<VaultResponse> {
-    get_vault(State(state), Path(owner)).await
+    query: Query<MintQuery>,
+) -> Result<AxumJson<VaultResponse>, (StatusCode, String)> {
+    get_vault(State(state), Path(owner), query).await
 }
 
 fn instruction_discriminator(name: &str) -> [u8; 8] {
@@ -145,25 +150,25 @@ fn instruction_discriminator(name: &str) -> [u8; 8] {
 pub async fn tx_deposit(
     Json(req): Json<AmountRequest>,
 ) -> Json<IntentResponse> {
-    Json(deposit_intent(req.amount))
+    Json(deposit_intent(&req.mint, req.amount))
 }
 
 pub async fn tx_withdraw(
     Json(req): Json<AmountRequest>,
 ) -> Json<IntentResponse> {
-    Json(withdraw_intent(req.amount))
+    Json(withdraw_intent(&req.mint, req.amount))
 }
 
 pub async fn tx_lock(Json(req): Json<AmountRequest>) -> AxumJson<IntentResponse> {
-    AxumJson(lock_intent(req.amount))
+    AxumJson(lock_intent(&req.mint, req.amount))
 }
 
 pub async fn tx_unlock(Json(req): Json<AmountRequest>) -> AxumJson<IntentResponse> {
-    AxumJson(unlock_intent(req.amount))
+    AxumJson(unlock_intent(&req.mint, req.amount))
 }
 
 pub async fn tx_transfer(Json(req): Json<TransferRequest>) -> AxumJson<IntentResponse> {
-    AxumJson(transfer_intent(&req.from, &req.to, req.amount))
+    AxumJson(transfer_intent(&req.from, &req.to, &req.mint, req.amount))
 }
 
 pub async fn confirm_tx(
@@ -171,9 +176,10 @@ pub async fn confirm_tx(
     Json(req): Json<ConfirmRequest>,
 ) -> AxumJson<String> {
     sqlx::query(
-        "INSERT INTO vault_transactions (owner, tx_type, amount, signature, timestamp) VALUES ($1, $2, $3, $4, NOW())"
+        "INSERT INTO vault_transactions (owner, mint, tx_type, amount, signature, timestamp) VALUES ($1, $2, $3, $4, $5, NOW())"
     )
     .bind(req.owner)
+    .bind(req.mint)
     .bind(req.event_type)
     .bind(req.amount as i64)
     .bind(req.sig)
@@ -4,26 +4,85 @@ use solana_sdk::pubkey::Pubkey;
 use std::str::FromStr;
 use backoff::{ExponentialBackoff, retry};
 use borsh::BorshDeserialize;
-use crate::models::CollateralVaultAccount;
+use crate::models::{CollateralVaultAccount, LegacyCollateralVaultAccount};
 
 pub const RPC_URL: &str = "http://127.0.0.1:8899";
 pub const PROGRAM_ID: &str = "CqYzY3dRdbEBUg29TFBWXLrQQhumMeyRr6vJv76RNiTq";
 
-pub fn fetch_vault(owner: &Pubkey) -> Result<(Pubkey, CollateralVaultAccount)> {
+/// Highest `CollateralVault` layout version this decoder understands
+pub const MAX_VAULT_VERSION: u8 = 1;
+
+/// Fetches the owner's vault for `mint`. Until the owner runs
+/// `migrate_vault`, that is the legacy vault at `["vault", owner]` if its
+/// token account holds `mint`; it decodes with version 0.
+pub fn fetch_vault(owner: &Pubkey, mint: &Pubkey) -> Result<(Pubkey, CollateralVaultAccount)> {
     let client = RpcClient::new(RPC_URL.to_string());
     let program_id = Pubkey::from_str(PROGRAM_ID)?;
 
     let (vault_pda, _) = Pubkey::find_program_address(
-        &[b"vault", owner.as_ref()],
+        &[b"vault", owner.as_ref(), mint.as_ref()],
         &program_id,
     );
 
-    let account = retry(ExponentialBackoff::default(), || {
-        client.get_account(&vault_pda).map_err(backoff::Error::transient)
+    if let Some(data) = fetch_account_data(&client, &vault_pda)? {
+        return Ok((vault_pda, decode_vault(&data)?));
+    }
+
+    let (legacy_pda, _) = Pubkey::find_program_address(&[b"vault", owner.as_ref()], &program_id);
+    let data = fetch_account_data(&client, &legacy_pda)?
+        .ok_or_else(|| anyhow!("No vault for {} and mint {}", owner, mint))?;
+    let mut vault = decode_vault(&data)?;
+    if vault.version != 0 {
+        return Err(anyhow!("Unexpected account at legacy vault address {}", legacy_pda));
+    }
+
+    // The SPL token account layout starts with its mint
+    let token_account = fetch_account_data(&client, &Pubkey::new_from_array(vault.token_account))?
+        .ok_or_else(|| anyhow!("Legacy vault {} has no token account", legacy_pda))?;
+    if token_account.get(..32) != Some(mint.as_ref()) {
+        return Err(anyhow!("No vault for {} and mint {}", owner, mint));
+    }
+    vault.mint = mint.to_bytes();
+
+    Ok((legacy_pda, vault))
+}
+
+/// `None` when the account does not exist; RPC errors are retried.
+fn fetch_account_data(client: &RpcClient, address: &Pubkey) -> Result<Option<Vec<u8>>> {
+    let response = retry(ExponentialBackoff::default(), || {
+        client
+            .get_account_with_commitment(address, client.commitment())
+            .map_err(backoff::Error::transient)
     })?;
 
-    let mut data: &[u8] = &account.data;
-    let vault = CollateralVaultAccount::deserialize(&mut data)?;
+    Ok(response.value.map(|account| account.data))
+}
+
+/// Size of a legacy vault account: discriminator, owner, token_account,
+/// five balances, created_at and bump
+pub const LEGACY_VAULT_LEN: usize = 8 + 32 + 32 + 5 * 8 + 8 + 1;
+
+/// Decodes a `CollateralVault`, or a legacy vault (`LEGACY_VAULT_LEN` bytes,
+/// no mint) still waiting for `migrate_vault`, reported as version 0.
+pub fn decode_vault(data: &[u8]) -> Result<CollateralVaultAccount> {
+    // Skip the 8-byte Anchor discriminator
+    let mut body: &[u8] = data
+        .get(8..)
+        .ok_or_else(|| anyhow!("Vault account too short"))?;
+
+    if data.len() == LEGACY_VAULT_LEN {
+        return Ok(LegacyCollateralVaultAccount::deserialize(&mut body)?.into());
+    }
+
+    let mut vault = CollateralVaultAccount::deserialize(&mut body)?;
+    vault.version = body
+        .first()
+        .copied()
+        .ok_or_else(|| anyhow!("Vault account has no version byte"))?;
+
+    if vault.version == 0 || vault.version > MAX_VAULT_VERSION {
+        return Err(anyhow!("Unsupported vault layout version {}", vault.version));
+    }
 
-    Ok((vault_pda, vault))
-}
\ No newline at end of file
+    Ok(vault)
+}
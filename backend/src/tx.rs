@@ -4,47 +4,47 @@ use crate::models::IntentResponse;
 const PROGRAM: &str = "collateral_vault";
 const NOTE: &str = "Client must build and sign the Anchor instruction";
 
-pub fn deposit_intent(amount: u64) -> IntentResponse {
+pub fn deposit_intent(mint: &str, amount: u64) -> IntentResponse {
     IntentResponse {
         program: PROGRAM,
         instruction: "deposit",
-        params: json!({ "amount": amount }),
+        params: json!({ "mint": mint, "amount": amount }),
         note: NOTE,
     }
 }
 
-pub fn withdraw_intent(amount: u64) -> IntentResponse {
+pub fn withdraw_intent(mint: &str, amount: u64) -> IntentResponse {
     IntentResponse {
         program: PROGRAM,
         instruction: "withdraw",
-        params: json!({ "amount": amount }),
+        params: json!({ "mint": mint, "amount": amount }),
         note: NOTE,
     }
 }
 
-pub fn lock_intent(amount: u64) -> IntentResponse {
+pub fn lock_intent(mint: &str, amount: u64) -> IntentResponse {
     IntentResponse {
         program: PROGRAM,
         instruction: "lock_collateral",
-        params: json!({ "amount": amount }),
+        params: json!({ "mint": mint, "amount": amount }),
         note: NOTE,
     }
 }
 
-pub fn unlock_intent(amount: u64) -> IntentResponse {
+pub fn unlock_intent(mint: &str, amount: u64) -> IntentResponse {
     IntentResponse {
         program: PROGRAM,
         instruction: "unlock_collateral",
-        params: json!({ "amount": amount }),
+        params: json!({ "mint": mint, "amount": amount }),
         note: NOTE,
     }
 }
 
-pub fn transfer_intent(from: &str, to: &str, amount: u64) -> IntentResponse {
+pub fn transfer_intent(from: &str, to: &str, mint: &str, amount: u64) -> IntentResponse {
     IntentResponse {
         program: PROGRAM,
         instruction: "transfer_collateral",
-        params: json!({ "from": from, "to": to, "amount": amount }),
+        params: json!({ "from": from, "to": to, "mint": mint, "amount": amount }),
         note: NOTE,
     }
 }
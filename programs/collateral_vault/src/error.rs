@@ -16,4 +16,154 @@ pub enum VaultError {
 
     #[msg("Cannot withdraw all with open positions")]
     OpenPositionsExist,
+
+    #[msg("Too many authorized programs")]
+    TooManyAuthorizedPrograms,
+
+    #[msg("Program is already authorized")]
+    ProgramAlreadyAuthorized,
+
+    #[msg("Program is not authorized")]
+    ProgramNotAuthorized,
+
+    #[msg("No pending admin for this signer")]
+    NoPendingAdmin,
+
+    #[msg("Insufficient locked balance")]
+    InsufficientLockedBalance,
+
+    #[msg("Source and destination vault must differ")]
+    SameVault,
+
+    #[msg("Locked transfers need the position's lock record")]
+    LockRecordRequired,
+
+    #[msg("Rent receiver does not match the lock record")]
+    InvalidRentReceiver,
+
+    #[msg("Mint has a Token-2022 extension the vault does not support")]
+    UnsupportedMintExtension,
+
+    #[msg("This instruction is paused")]
+    Paused,
+
+    #[msg("Unknown pause flag")]
+    InvalidPauseFlags,
+
+    #[msg("Vault still holds collateral")]
+    VaultNotEmpty,
+
+    #[msg("Account is not a collateral vault")]
+    InvalidVaultAccount,
+
+    #[msg("Insufficient insurance fund balance")]
+    InsufficientInsuranceFund,
+
+    #[msg("Amount exceeds recorded bad debt")]
+    BadDebtExceeded,
+
+    #[msg("Withdrawals must go through request_withdrawal")]
+    InstantWithdrawDisabled,
+
+    #[msg("No pending withdrawal")]
+    NoPendingWithdrawal,
+
+    #[msg("Withdrawal cooldown has not elapsed")]
+    WithdrawalCooldownActive,
+
+    #[msg("Pending withdrawal is blocked")]
+    WithdrawalBlocked,
+
+    #[msg("Invalid cooldown")]
+    InvalidCooldown,
+
+    #[msg("Deposit exceeds the per-transaction cap")]
+    DepositTooLarge,
+
+    #[msg("Deposit would exceed the per-vault cap")]
+    VaultCapExceeded,
+
+    #[msg("Deposit would exceed the TVL cap")]
+    TvlCapExceeded,
+
+    #[msg("Withdrawal limit exceeded for the current window")]
+    WithdrawalLimitExceeded,
+
+    #[msg("Invalid withdrawal limit")]
+    InvalidWithdrawalLimit,
+
+    #[msg("Invalid lock expiry")]
+    InvalidExpiry,
+
+    #[msg("Lock has not expired")]
+    LockNotExpired,
+
+    #[msg("Emergency mode is not active")]
+    EmergencyModeInactive,
+
+    #[msg("Invalid inactivity period")]
+    InvalidInactivityPeriod,
+
+    #[msg("Destination is not on the vault's allowlist")]
+    DestinationNotAllowed,
+
+    #[msg("Destination is already on the allowlist")]
+    DestinationAlreadyAllowed,
+
+    #[msg("Too many allowlisted destinations")]
+    TooManyDestinations,
+
+    #[msg("Vault has an allowlist; pass it")]
+    AllowlistRequired,
+
+    #[msg("Delegate has expired")]
+    DelegateExpired,
+
+    #[msg("Delegate lacks this permission")]
+    DelegatePermissionDenied,
+
+    #[msg("Delegate withdrawal cap exceeded")]
+    DelegateCapExceeded,
+
+    #[msg("Invalid delegate settings")]
+    InvalidDelegate,
+
+    #[msg("Withdrawal above the threshold needs the vault guardian's signature")]
+    GuardianSignatureRequired,
+
+    #[msg("Source and destination sub-accounts are the same")]
+    SameSubAccount,
+
+    #[msg("Oracle account is not a valid trading price feed")]
+    InvalidOracle,
+
+    #[msg("Oracle price is stale")]
+    StaleOraclePrice,
+
+    #[msg("Invalid collateral config")]
+    InvalidCollateralConfig,
+
+    #[msg("Return data is not a vault state snapshot")]
+    InvalidVaultStateData,
+
+    #[msg("Vault has bad debt outstanding")]
+    OutstandingBadDebt,
+
+    #[msg("TVL counter was already reconciled")]
+    TvlAlreadyReconciled,
+
+    #[msg("Lock record predates an emergency withdrawal")]
+    StaleLockRecord,
+
+    #[msg("Sub-account predates an emergency withdrawal")]
+    StaleSubAccount,
+
+    #[msg("Close the vault's lock records, sub-accounts and delegates first")]
+    OpenVaultRecords,
+
+    #[msg("Sub-account still holds collateral or locks")]
+    SubAccountNotEmpty,
+
+    #[msg("Account is not a legacy vault authority")]
+    InvalidVaultAuthorityAccount,
 }
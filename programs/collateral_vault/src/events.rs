@@ -1,10 +1,14 @@
 use anchor_lang::prelude::*;
 
+use crate::state::{InsuranceDrawReason, TransferMode};
+
 #[event]
 pub struct DepositEvent {
-    pub user: Pubkey,
+    pub user: Pubkey,              // Vault owner (beneficiary)
+    pub funder: Pubkey,            // Signer whose tokens were deposited
     pub vault: Pubkey,
     pub amount: u64,
+    pub fee: u64,  // Token-2022 transfer fee withheld from `amount`
     pub new_total_balance: u64,
     pub timestamp: i64,
 }
@@ -13,7 +17,9 @@ pub struct DepositEvent {
 pub struct WithdrawEvent {
     pub user: Pubkey,
     pub vault: Pubkey,
+    pub destination: Pubkey,       // Token account that received the funds
     pub amount: u64,
+    pub fee: u64,  // Token-2022 transfer fee withheld from `amount`
     pub new_total_balance: u64,
     pub timestamp: i64,
 }
@@ -21,16 +27,25 @@ pub struct WithdrawEvent {
 #[event]
 pub struct TransferEvent {
     pub from_vault: Pubkey,
+    pub from_sub_account: Option<u16>, // Index when drawn from a sub-account
     pub to_vault: Pubkey,
     pub amount: u64,
+    pub fee: u64,                    // Withheld by the mint; `to_vault` got `amount - fee`
+    pub mode: TransferMode,          // Pool the funds came from
+    pub position_id: Option<u64>,    // Set for `TransferMode::Locked`
+    pub from_available_balance: u64, // The sub-account's, when drawn from one
+    pub from_locked_balance: u64,
     pub timestamp: i64,
 }
 
 #[event]
 pub struct LockEvent {
     pub vault: Pubkey,
+    pub sub_account: Option<u16>,  // Sub-account index; None for the vault itself
     pub caller: Pubkey,  // Log who (which program) locked
+    pub position_id: u64,
     pub amount: u64,
+    pub position_locked: u64,
     pub new_locked_balance: u64,
     pub timestamp: i64,
 }
@@ -38,8 +53,356 @@ pub struct LockEvent {
 #[event]
 pub struct UnlockEvent {
     pub vault: Pubkey,
+    pub sub_account: Option<u16>,  // Sub-account index; None for the vault itself
     pub caller: Pubkey,
+    pub position_id: u64,
     pub amount: u64,
+    pub position_locked: u64,
     pub new_locked_balance: u64,
+    pub expired: bool,             // Released by `release_expired_lock`
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultAuthorityInitializedEvent {
+    pub admin: Pubkey,
+    pub authorized_programs: Vec<Pubkey>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultAuthorityMigratedEvent {
+    pub admin: Pubkey,
+    pub authorized_programs: Vec<Pubkey>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuthorizedProgramAddedEvent {
+    pub admin: Pubkey,
+    pub program: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuthorizedProgramRemovedEvent {
+    pub admin: Pubkey,
+    pub program: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AdminProposedEvent {
+    pub admin: Pubkey,
+    pub pending_admin: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AdminTransferredEvent {
+    pub previous_admin: Pubkey,
+    pub new_admin: Pubkey,
     pub timestamp: i64,
-}
\ No newline at end of file
+}
+
+#[event]
+pub struct GlobalConfigInitializedEvent {
+    pub admin: Pubkey,
+    pub guardian: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct GuardianUpdatedEvent {
+    pub admin: Pubkey,
+    pub previous_guardian: Pubkey,
+    pub new_guardian: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PausedEvent {
+    pub authority: Pubkey,
+    pub flags: u32,   // Flags paused by this call
+    pub paused: u32,  // Full bitmask afterwards
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct UnpausedEvent {
+    pub authority: Pubkey,
+    pub flags: u32,
+    pub paused: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultClosedEvent {
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub mint: Pubkey,
+    pub swept_amount: u64,  // Untracked tokens returned to the owner before closing
+    pub harvested_fees: u64, // Token-2022 withheld fees moved to the mint before closing
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultMigratedEvent {
+    pub legacy_vault: Pubkey,
+    pub vault: Pubkey,
+    pub from_version: u8,
+    pub to_version: u8,
+    pub total_balance: u64,
+    pub locked_balance: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LiquidationEvent {
+    pub vault: Pubkey,
+    pub sub_account: Option<u16>,  // Index when the position was locked in a sub-account
+    pub caller: Pubkey,            // Program that liquidated
+    pub position_id: u64,
+    pub liquidator: Pubkey,        // Owner of the reward token account
+    pub liquidator_reward: u64,
+    pub insurance_fee: u64,
+    pub position_locked: u64,      // Left on the lock record afterwards
+    pub new_total_balance: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct InsuranceFundInitializedEvent {
+    pub admin: Pubkey,
+    pub insurance_fund: Pubkey,
+    pub mint: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct InsuranceFundDepositEvent {
+    pub funder: Pubkey,
+    pub insurance_fund: Pubkey,
+    pub amount: u64,
+    pub new_fund_balance: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct InsuranceDrawEvent {
+    pub insurance_fund: Pubkey,
+    pub vault: Pubkey,             // Vault credited with the draw
+    pub caller: Pubkey,            // Program that drew
+    pub amount: u64,
+    pub reason: InsuranceDrawReason,
+    pub new_fund_balance: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BadDebtRecordedEvent {
+    pub vault: Pubkey,
+    pub caller: Pubkey,
+    pub amount: u64,
+    pub new_bad_debt: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BadDebtCoveredEvent {
+    pub vault: Pubkey,             // Vault whose bad debt was reduced
+    pub recipient_vault: Pubkey,   // Vault credited with the cover
+    pub caller: Pubkey,
+    pub amount: u64,
+    pub new_bad_debt: u64,
+    pub new_fund_balance: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LossSocializedEvent {
+    pub vault: Pubkey,             // Vault that absorbed a share of the loss
+    pub debtor_vault: Pubkey,
+    pub recipient_vault: Pubkey,
+    pub amount: u64,
+    pub new_total_balance: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BadDebtSocializedEvent {
+    pub vault: Pubkey,             // Vault whose bad debt was reduced
+    pub recipient_vault: Pubkey,
+    pub caller: Pubkey,
+    pub amount: u64,
+    pub vault_count: u32,
+    pub new_bad_debt: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct WithdrawalCooldownUpdatedEvent {
+    pub admin: Pubkey,
+    pub previous_cooldown: i64,
+    pub new_cooldown: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct WithdrawalRequestedEvent {
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub amount: u64,
+    pub pending_withdrawal: u64,
+    pub claimable_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct WithdrawalCancelledEvent {
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub amount: u64,
+    pub new_available_balance: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct WithdrawalBlockSetEvent {
+    pub vault: Pubkey,
+    pub caller: Pubkey,
+    pub blocked: bool,
+    pub pending_withdrawal: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DepositCapsUpdatedEvent {
+    pub admin: Pubkey,
+    pub max_deposit_per_tx: u64,
+    pub max_vault_balance: u64,
+    pub max_tvl: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct WithdrawalLimitUpdatedEvent {
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub limit: u64,
+    pub window: i64,
+    pub effective_at: i64,         // Equals timestamp when applied immediately
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct EmergencyModeUpdatedEvent {
+    pub admin: Pubkey,
+    pub emergency_mode: bool,
+    pub inactivity_period: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct EmergencyWithdrawEvent {
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub amount: u64,
+    pub fee: u64,                  // Withheld by a Token-2022 transfer fee
+    pub locked_released: u64,      // Part of `amount` that was locked
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct WithdrawDestinationAddedEvent {
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub destination: Pubkey,
+    pub active_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct WithdrawDestinationRemovedEvent {
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub destination: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DelegateSetEvent {
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub delegate: Pubkey,
+    pub permissions: u8,
+    pub withdraw_cap: u64,
+    pub expires_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DelegateRevokedEvent {
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub delegate: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultGuardianUpdatedEvent {
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub guardian: Pubkey,
+    pub cosign_threshold: u64,
+    pub effective_at: i64,         // Equals timestamp when applied immediately
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SubAccountCreatedEvent {
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub sub_account: Pubkey,
+    pub index: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SubAccountClosedEvent {
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub sub_account: Pubkey,
+    pub index: u16,
+    pub stale: bool,     // Closed with a balance an emergency withdrawal already paid out
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SubAccountTransferEvent {
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub from_index: Option<u16>,   // None = the vault's own available balance
+    pub to_index: Option<u16>,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CollateralConfigUpdatedEvent {
+    pub admin: Pubkey,
+    pub mint: Pubkey,
+    pub oracle: Pubkey,
+    pub haircut_bps: u16,
+    pub max_staleness: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TvlReconciledEvent {
+    pub admin: Pubkey,
+    pub mint: Pubkey,
+    pub previous: u64,
+    pub total_value_locked: u64,
+    pub timestamp: i64,
+}
@@ -5,16 +5,34 @@ use anchor_spl::{
         Mint,
         TokenAccount,
         TokenInterface,
+        CloseAccount,
         TransferChecked,
     },
 };
-use anchor_lang::prelude::log;
+use anchor_spl::token_2022::{
+    self as token_2022,
+    spl_token_2022::{
+        extension::{
+            transfer_fee::TransferFeeAmount,
+            BaseStateWithExtensions,
+            ExtensionType,
+            StateWithExtensions,
+        },
+        state::{Account as TokenAccountState, Mint as MintState},
+    },
+};
+use anchor_spl::token_2022_extensions::{
+    harvest_withheld_tokens_to_mint,
+    HarvestWithheldTokensToMint,
+};
 use anchor_lang::prelude::InterfaceAccount;
 use anchor_lang::prelude::*;
+use anchor_lang::system_program;
 use anchor_spl::associated_token::AssociatedToken;
 pub mod state;
 pub mod error;
 pub mod events;
+pub mod oracle;
 
 
 use state::*;
@@ -31,11 +49,19 @@ use events::*;
 
 declare_id!("3gRnkhd9M4FrZYeTCZ9vAb42UYR5xeeM6qBZMUMiJdJq");
 
+/// Seed of the `caller_authority` PDA an authorized program signs its CPIs
+/// with. Only the program that derives it can sign for it (via
+/// `invoke_signed`), so passing a whitelisted program ID alone is not enough.
+#[constant]
+pub const CALLER_AUTHORITY_SEED: &[u8] = b"vault_caller";
+
 #[program]
 pub mod collateral_vault {
     use super::*;
 
     pub fn initialize_vault(ctx: Context<InitializeVault>) -> Result<()> {
+        ensure_supported_mint(&ctx.accounts.token_mint.to_account_info())?;
+
         // Create associated token account for the vault PDA first (uses immutable AccountInfo)
         associated_token::create(
             CpiContext::new(
@@ -54,8 +80,9 @@ pub mod collateral_vault {
         // Now mutably borrow and set fields
         let vault = &mut ctx.accounts.vault;
 
-        // Set authority and token account
+        // Set authority, mint and token account
         vault.owner = ctx.accounts.user.key();
+        vault.mint = ctx.accounts.token_mint.key();
         vault.token_account = ctx.accounts.vault_token_account.key();
 
         // Genesis invariants
@@ -69,6 +96,34 @@ pub mod collateral_vault {
         vault.created_at = Clock::get()?.unix_timestamp;
         vault.bump = ctx.bumps.vault;
 
+        vault.version = CollateralVault::CURRENT_VERSION;
+        vault.bad_debt = 0;
+        vault.pending_withdrawal = 0;
+        vault.withdrawal_claimable_at = 0;
+        vault.withdrawal_blocked = false;
+        vault.withdrawal_limit = 0;
+        vault.withdrawal_window = 0;
+        vault.window_start = 0;
+        vault.withdrawn_in_window = 0;
+        vault.pending_withdrawal_limit = 0;
+        vault.pending_withdrawal_window = 0;
+        vault.pending_limit_effective_at = 0;
+        vault.allowlist_enabled = false;
+        vault.guardian = Pubkey::default();
+        vault.cosign_threshold = 0;
+        vault.pending_guardian = Pubkey::default();
+        vault.pending_cosign_threshold = 0;
+        vault.guardian_effective_at = 0;
+        vault.sub_account_balance = 0;
+        vault.epoch = 0;
+        vault.lock_count = 0;
+        vault.sub_account_count = 0;
+        vault.delegate_count = 0;
+        vault.pending_in_window = 0;
+        vault.withdrawn_in_prev_window = 0;
+        vault.pending_in_prev_window = 0;
+        vault.reserved = [0; 42];
+
         Ok(())
     }
     pub fn deposit(ctx: Context<Deposit>, amount: u64) -> Result<()> {
@@ -76,44 +131,79 @@ pub mod collateral_vault {
 
         let vault_key = ctx.accounts.vault.key();
         let user_key = ctx.accounts.user.key();
+        let owner_key = ctx.accounts.vault.owner;
         let now = Clock::get()?.unix_timestamp;
 
-        let vault = &mut ctx.accounts.vault;
+        if user_key != owner_key {
+            let delegate = ctx
+                .accounts
+                .delegate
+                .as_ref()
+                .ok_or(VaultError::Unauthorized)?;
+            delegate.authorize(Delegate::PERMISSION_DEPOSIT, now)?;
+        }
 
-        // SPL transfer (checked)
-        token_interface::transfer_checked(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                TransferChecked {
-                    from: ctx.accounts.user_token_account.to_account_info(),
-                    to: ctx.accounts.vault_token_account.to_account_info(),
-                    authority: ctx.accounts.user.to_account_info(),
-                    mint: ctx.accounts.mint.to_account_info(),
-                },
-            ),
+        let received = pull_into_vault(
+            &ctx.accounts.user_token_account,
+            &ctx.accounts.user,
+            &mut ctx.accounts.vault_token_account,
+            &ctx.accounts.mint,
+            &ctx.accounts.token_program,
             amount,
-            ctx.accounts.mint.decimals,
         )?;
 
-        vault.total_balance = vault
-            .total_balance
-            .checked_add(amount)
-            .ok_or(VaultError::MathOverflow)?;
+        let tvl = &mut ctx.accounts.tvl_counter;
+        tvl.mint = ctx.accounts.mint.key();
+        tvl.bump = ctx.bumps.tvl_counter;
 
-        vault.available_balance = vault
-            .available_balance
-            .checked_add(amount)
-            .ok_or(VaultError::MathOverflow)?;
+        let vault = &mut ctx.accounts.vault;
+        vault.credit_deposit(tvl, &ctx.accounts.global_config, amount, received)?;
 
-        vault.total_deposited = vault
-            .total_deposited
-            .checked_add(amount)
-            .ok_or(VaultError::MathOverflow)?;
+        emit!(DepositEvent {
+            user: owner_key,
+            funder: user_key,
+            vault: vault_key,
+            amount: received,
+            fee: amount.saturating_sub(received),
+            new_total_balance: vault.total_balance,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Deposits the funder's tokens into someone else's vault. Caps apply as
+    /// for `deposit`; only the vault owner can take the funds out.
+    pub fn deposit_for(ctx: Context<DepositFor>, amount: u64) -> Result<()> {
+        require!(amount > 0, VaultError::InvalidAmount);
+
+        let vault_key = ctx.accounts.vault.key();
+        let user_key = ctx.accounts.vault.owner;
+        let funder_key = ctx.accounts.funder.key();
+        let now = Clock::get()?.unix_timestamp;
+
+        let received = pull_into_vault(
+            &ctx.accounts.funder_token_account,
+            &ctx.accounts.funder,
+            &mut ctx.accounts.vault_token_account,
+            &ctx.accounts.mint,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+
+        let tvl = &mut ctx.accounts.tvl_counter;
+        tvl.mint = ctx.accounts.mint.key();
+        tvl.bump = ctx.bumps.tvl_counter;
+
+        let vault = &mut ctx.accounts.vault;
+        vault.credit_deposit(tvl, &ctx.accounts.global_config, amount, received)?;
 
         emit!(DepositEvent {
             user: user_key,
+            funder: funder_key,
             vault: vault_key,
-            amount,
+            amount: received,
+            fee: amount.saturating_sub(received),
             new_total_balance: vault.total_balance,
             timestamp: now,
         });
@@ -123,69 +213,115 @@ pub mod collateral_vault {
 
     pub fn withdraw(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
         require!(amount > 0, VaultError::InvalidAmount);
+        require!(
+            ctx.accounts.global_config.withdrawal_cooldown == 0,
+            VaultError::InstantWithdrawDisabled
+        );
 
         let vault_key = ctx.accounts.vault.key();
-        let vault_ai = ctx.accounts.vault.to_account_info();
         let user_key = ctx.accounts.user.key();
+        let owner_key = ctx.accounts.vault.owner;
         let now = Clock::get()?.unix_timestamp;
 
+        // Delegates withdraw here only to the owner's accounts, within their cap
+        if user_key != owner_key {
+            let delegate = ctx
+                .accounts
+                .delegate
+                .as_mut()
+                .ok_or(VaultError::Unauthorized)?;
+            delegate.authorize(Delegate::PERMISSION_WITHDRAW, now)?;
+            delegate.record_withdrawal(amount)?;
+        }
+
         let vault = &mut ctx.accounts.vault;
+        vault.check_instant_withdrawal(
+            amount,
+            ctx.accounts.guardian.as_ref().map(|g| g.key()),
+            now,
+        )?;
+
+        // Debit what left the vault; the user may receive less on fee mints
+        let (debited, received) = pay_out_of_vault(
+            vault,
+            &mut ctx.accounts.vault_token_account,
+            &mut ctx.accounts.user_token_account,
+            &ctx.accounts.mint,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+        vault.debit_withdrawal(&mut ctx.accounts.tvl_counter, debited)?;
+
+        emit!(WithdrawEvent {
+            user: owner_key,
+            vault: vault_key,
+            destination: ctx.accounts.user_token_account.key(),
+            amount: debited,
+            fee: debited.saturating_sub(received),
+            new_total_balance: vault.total_balance,
+            timestamp: now,
+        });
 
-        
+        Ok(())
+    }
+
+    /// Like `withdraw`, but to any token account of the mint. Once the owner
+    /// has an allowlist, the destination's owner must be an active entry.
+    /// Delegates may only pay allowlisted destinations, within their cap.
+    pub fn withdraw_to(ctx: Context<WithdrawTo>, amount: u64) -> Result<()> {
+        require!(amount > 0, VaultError::InvalidAmount);
         require!(
-            vault.available_balance >= amount,
-            VaultError::InsufficientAvailableBalance
+            ctx.accounts.global_config.withdrawal_cooldown == 0,
+            VaultError::InstantWithdrawDisabled
         );
 
-        //extra check
-        if amount == vault.total_balance {
-            require!(vault.locked_balance == 0, VaultError::OpenPositionsExist);
+        let vault_key = ctx.accounts.vault.key();
+        let user_key = ctx.accounts.user.key();
+        let owner_key = ctx.accounts.vault.owner;
+        let now = Clock::get()?.unix_timestamp;
+
+        let by_delegate = user_key != owner_key;
+        if by_delegate {
+            let delegate = ctx
+                .accounts
+                .delegate
+                .as_mut()
+                .ok_or(VaultError::Unauthorized)?;
+            delegate.authorize(Delegate::PERMISSION_WITHDRAW, now)?;
+            delegate.record_withdrawal(amount)?;
         }
 
-        // PDA signer seeds
-        let seeds = &[
-            b"vault",
-            vault.owner.as_ref(),
-            &[vault.bump],
-        ];
-        let signer = &[&seeds[..]];
+        ctx.accounts.vault.check_withdraw_destination(
+            ctx.accounts.withdraw_allowlist.as_deref(),
+            &ctx.accounts.destination_token_account.owner,
+            by_delegate,
+            now,
+        )?;
 
-        // SPL token transfer: vault → user (checked)
-        token_interface::transfer_checked(
-            CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                TransferChecked {
-                    from: ctx.accounts.vault_token_account.to_account_info(),
-                    to: ctx.accounts.user_token_account.to_account_info(),
-                    authority: vault_ai,
-                    mint: ctx.accounts.mint.to_account_info(),
-                },
-                signer,
-            ),
+        let vault = &mut ctx.accounts.vault;
+        vault.check_instant_withdrawal(
             amount,
-            ctx.accounts.mint.decimals,
+            ctx.accounts.guardian.as_ref().map(|g| g.key()),
+            now,
         )?;
 
-        // Update balances (checked math)
-        vault.total_balance = vault
-            .total_balance
-            .checked_sub(amount)
-            .ok_or(VaultError::MathOverflow)?;
-
-        vault.available_balance = vault
-            .available_balance
-            .checked_sub(amount)
-            .ok_or(VaultError::MathOverflow)?;
-
-        vault.total_withdrawn = vault
-            .total_withdrawn
-            .checked_add(amount)
-            .ok_or(VaultError::MathOverflow)?;
+        // Debit what left the vault; the destination may receive less on fee mints
+        let (debited, received) = pay_out_of_vault(
+            vault,
+            &mut ctx.accounts.vault_token_account,
+            &mut ctx.accounts.destination_token_account,
+            &ctx.accounts.mint,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+        vault.debit_withdrawal(&mut ctx.accounts.tvl_counter, debited)?;
 
         emit!(WithdrawEvent {
-            user: user_key,
+            user: owner_key,
             vault: vault_key,
-            amount,
+            destination: ctx.accounts.destination_token_account.key(),
+            amount: debited,
+            fee: debited.saturating_sub(received),
             new_total_balance: vault.total_balance,
             timestamp: now,
         });
@@ -193,191 +329,3635 @@ pub mod collateral_vault {
         Ok(())
     }
 
-    pub fn initialize_vault_authority(
-        ctx: Context<InitializeVaultAuthority>,
-        authorized_programs: Vec<Pubkey>,
+    /// Adds a `withdraw_to` destination wallet, usable after
+    /// `WithdrawAllowlist::ACTIVATION_DELAY`. The first entry turns the allowlist on.
+    pub fn add_withdraw_destination(
+        ctx: Context<AddWithdrawDestination>,
+        destination: Pubkey,
     ) -> Result<()> {
-        require!(
-            authorized_programs.len() <= 16,
-            VaultError::InvalidAmount
-        );
+        let now = Clock::get()?.unix_timestamp;
+        let vault = &mut ctx.accounts.vault;
+        let allowlist = &mut ctx.accounts.withdraw_allowlist;
 
-        let authority = &mut ctx.accounts.vault_authority;
-        authority.authorized_programs = authorized_programs;
-        authority.bump = ctx.bumps.vault_authority;
+        allowlist.vault = vault.key();
+        allowlist.bump = ctx.bumps.withdraw_allowlist;
+        let active_at = allowlist.add_destination(destination, now)?;
+
+        vault.allowlist_enabled = true;
+
+        emit!(WithdrawDestinationAddedEvent {
+            user: ctx.accounts.user.key(),
+            vault: vault.key(),
+            destination,
+            active_at,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Removing a destination takes effect immediately.
+    pub fn remove_withdraw_destination(
+        ctx: Context<RemoveWithdrawDestination>,
+        destination: Pubkey,
+    ) -> Result<()> {
+        ctx.accounts.withdraw_allowlist.remove_destination(&destination)?;
+
+        emit!(WithdrawDestinationRemovedEvent {
+            user: ctx.accounts.user.key(),
+            vault: ctx.accounts.vault.key(),
+            destination,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
 
         Ok(())
     }
 
-    pub fn lock_collateral(ctx: Context<LockCollateral>, amount: u64) -> Result<()> {
+    /// First step of a timelocked withdrawal: moves `amount` from available into
+    /// `pending_withdrawal`. Adding to an existing request restarts the cooldown.
+    /// The withdrawal limit is charged here, not at claim.
+    pub fn request_withdrawal(ctx: Context<RequestWithdrawal>, amount: u64) -> Result<()> {
         require!(amount > 0, VaultError::InvalidAmount);
 
-        let vault = &mut ctx.accounts.vault;
-        let caller_key = ctx.accounts.caller_program.key();
-        let vault_key = vault.key();
         let now = Clock::get()?.unix_timestamp;
+        let cooldown = ctx.accounts.global_config.withdrawal_cooldown;
+        let vault = &mut ctx.accounts.vault;
 
         require!(
             vault.available_balance >= amount,
             VaultError::InsufficientAvailableBalance
         );
 
+        vault.require_guardian_cosign(
+            amount,
+            ctx.accounts.guardian.as_ref().map(|g| g.key()),
+            now,
+        )?;
+        vault.record_pending_outflow(amount, now)?;
+
         vault.available_balance = vault
             .available_balance
             .checked_sub(amount)
             .ok_or(VaultError::MathOverflow)?;
 
-        vault.locked_balance = vault
-            .locked_balance
+        vault.pending_withdrawal = vault
+            .pending_withdrawal
             .checked_add(amount)
             .ok_or(VaultError::MathOverflow)?;
 
-        emit!(LockEvent {
-            vault: vault_key,
-            caller: caller_key,
+        vault.withdrawal_claimable_at = now
+            .checked_add(cooldown)
+            .ok_or(VaultError::MathOverflow)?;
+
+        emit!(WithdrawalRequestedEvent {
+            user: ctx.accounts.user.key(),
+            vault: vault.key(),
             amount,
-            new_locked_balance: vault.locked_balance,
+            pending_withdrawal: vault.pending_withdrawal,
+            claimable_at: vault.withdrawal_claimable_at,
             timestamp: now,
         });
 
         Ok(())
     }
 
-    pub fn unlock_collateral(ctx: Context<UnlockCollateral>, amount: u64) -> Result<()> {
-        require!(amount > 0, VaultError::InvalidAmount);
-
-        let vault = &mut ctx.accounts.vault;
-        let caller_key = ctx.accounts.caller_program.key();
-        let vault_key = vault.key();
+    /// Returns the whole pending withdrawal to the available balance and its
+    /// charge to the current window's outflow. A risk block stays in place
+    /// for the next request.
+    pub fn cancel_withdrawal(ctx: Context<RequestWithdrawal>) -> Result<()> {
         let now = Clock::get()?.unix_timestamp;
+        let vault = &mut ctx.accounts.vault;
+        let amount = vault.pending_withdrawal;
 
-        require!(
-            vault.locked_balance >= amount,
-            VaultError::InsufficientAvailableBalance
-        );
+        require!(amount > 0, VaultError::NoPendingWithdrawal);
 
-        vault.locked_balance = vault
-            .locked_balance
-            .checked_sub(amount)
-            .ok_or(VaultError::MathOverflow)?;
+        vault.refund_pending_outflow(now)?;
 
         vault.available_balance = vault
             .available_balance
             .checked_add(amount)
             .ok_or(VaultError::MathOverflow)?;
 
-        emit!(UnlockEvent {
-            vault: vault_key,
-            caller: caller_key,
+        vault.pending_withdrawal = 0;
+        vault.withdrawal_claimable_at = 0;
+
+        emit!(WithdrawalCancelledEvent {
+            user: ctx.accounts.user.key(),
+            vault: vault.key(),
             amount,
-            new_locked_balance: vault.locked_balance,
+            new_available_balance: vault.available_balance,
             timestamp: now,
         });
 
         Ok(())
     }
 
-    pub fn transfer_collateral(ctx: Context<TransferCollateral>, amount: u64) -> Result<()> {
-        require!(amount > 0, VaultError::InvalidAmount);
-
-        let from_vault = &mut ctx.accounts.from_vault;
-        let to_vault = &mut ctx.accounts.to_vault;
+    /// Second step: pays out the whole pending withdrawal once the cooldown
+    /// has elapsed and no risk program is holding it. Owner only.
+    pub fn claim_withdrawal(ctx: Context<Withdraw>) -> Result<()> {
+        let vault_key = ctx.accounts.vault.key();
+        let user_key = ctx.accounts.user.key();
         let now = Clock::get()?.unix_timestamp;
 
         require!(
-            from_vault.total_balance >= amount,
-            VaultError::InsufficientAvailableBalance
+            user_key == ctx.accounts.vault.owner,
+            VaultError::Unauthorized
         );
 
-        from_vault.total_balance = from_vault
-            .total_balance
-            .checked_sub(amount)
-            .ok_or(VaultError::MathOverflow)?;
+        let vault = &mut ctx.accounts.vault;
+        let amount = vault.pending_withdrawal;
 
-        from_vault.available_balance = from_vault
-            .available_balance
-            .checked_sub(amount)
-            .ok_or(VaultError::MathOverflow)?;
+        require!(amount > 0, VaultError::NoPendingWithdrawal);
+        require!(!vault.withdrawal_blocked, VaultError::WithdrawalBlocked);
+        require!(
+            now >= vault.withdrawal_claimable_at,
+            VaultError::WithdrawalCooldownActive
+        );
+
+        let (debited, received) = pay_out_of_vault(
+            vault,
+            &mut ctx.accounts.vault_token_account,
+            &mut ctx.accounts.user_token_account,
+            &ctx.accounts.mint,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
 
-        to_vault.total_balance = to_vault
+        vault.total_balance = vault
             .total_balance
-            .checked_add(amount)
+            .checked_sub(debited)
             .ok_or(VaultError::MathOverflow)?;
 
-        to_vault.available_balance = to_vault
-            .available_balance
-            .checked_add(amount)
+        vault.total_withdrawn = vault
+            .total_withdrawn
+            .checked_add(debited)
             .ok_or(VaultError::MathOverflow)?;
 
-        emit!(TransferEvent {
-            from_vault: from_vault.key(),
-            to_vault: to_vault.key(),
-            amount,
+        ctx.accounts.tvl_counter.sub(debited)?;
+
+        vault.pending_withdrawal = 0;
+        vault.clear_pending_outflow();
+        vault.withdrawal_claimable_at = 0;
+
+        emit!(WithdrawEvent {
+            user: user_key,
+            vault: vault_key,
+            destination: ctx.accounts.user_token_account.key(),
+            amount: debited,
+            fee: debited.saturating_sub(received),
+            new_total_balance: vault.total_balance,
             timestamp: now,
         });
 
         Ok(())
     }
 
-    pub fn demo_lock(ctx: Context<LockCollateral>, amount: u64) -> Result<()> {
-        lock_collateral(ctx, amount)
-    }
+    /// Grants (or replaces) a session key. Replacing resets its withdrawn amount.
+    pub fn set_delegate(
+        ctx: Context<SetDelegate>,
+        permissions: u8,
+        withdraw_cap: u64,
+        expires_at: i64,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
 
-    pub fn demo_unlock(ctx: Context<UnlockCollateral>, amount: u64) -> Result<()> {
-        unlock_collateral(ctx, amount)
-    }
+        require!(
+            permissions != 0 && permissions & !Delegate::PERMISSION_ALL == 0,
+            VaultError::InvalidDelegate
+        );
+        require!(expires_at > now, VaultError::InvalidExpiry);
 
-    pub fn demo_transfer_collateral(
-        ctx: Context<TransferCollateral>,
-        amount: u64,
-    ) -> Result<()> {
-        transfer_collateral(ctx, amount)
-    }
-}
+        let delegate = &mut ctx.accounts.delegate;
 
-#[derive(Accounts)]
-pub struct InitializeVault<'info> {
-    #[account(mut)]
-    pub user: Signer<'info>,
+        // Fresh delegate (init_if_needed leaves it zeroed)
+        if delegate.vault == Pubkey::default() {
+            let vault = &mut ctx.accounts.vault;
+            vault.delegate_count = vault
+                .delegate_count
+                .checked_add(1)
+                .ok_or(VaultError::MathOverflow)?;
+        }
 
-    #[account(
-        init,
-        payer = user,
-        space = CollateralVault::LEN,
-        seeds = [b"vault", user.key().as_ref()],
-        bump
-    )]
-    pub vault: Account<'info, CollateralVault>,
+        delegate.vault = ctx.accounts.vault.key();
+        delegate.delegate = ctx.accounts.delegate_key.key();
+        delegate.permissions = permissions;
+        delegate.withdraw_cap = withdraw_cap;
+        delegate.withdrawn = 0;
+        delegate.expires_at = expires_at;
+        delegate.bump = ctx.bumps.delegate;
 
-    /// CHECK: SPL token account that will hold USDT. Created via CPI.
-    #[account(mut)]
-    pub vault_token_account: UncheckedAccount<'info>,
+        emit!(DelegateSetEvent {
+            user: ctx.accounts.user.key(),
+            vault: delegate.vault,
+            delegate: delegate.delegate,
+            permissions,
+            withdraw_cap,
+            expires_at,
+            timestamp: now,
+        });
 
-    #[account(mut)]
-    pub token_mint: InterfaceAccount<'info, Mint>,  // USDT mint
+        Ok(())
+    }
 
-    pub associated_token_346: Program<'info, AssociatedToken>,
+    /// Revokes a session key immediately and refunds its rent to the owner.
+    pub fn revoke_delegate(ctx: Context<RevokeDelegate>) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        vault.delegate_count = vault
+            .delegate_count
+            .checked_sub(1)
+            .ok_or(VaultError::MathOverflow)?;
 
-    pub token_program: Interface<'info, TokenInterface>,
+        emit!(DelegateRevokedEvent {
+            user: ctx.accounts.user.key(),
+            vault: ctx.accounts.vault.key(),
+            delegate: ctx.accounts.delegate.delegate,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
 
-    pub system_program: Program<'info, System>,
-}
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct Deposit<'info> {
-    #[account(mut)]
-    pub user: Signer<'info>,
+    /// Owner sets (or schedules) the vault guardian that must co-sign
+    /// withdrawals above `threshold`. The default pubkey removes it.
+    pub fn set_vault_guardian(
+        ctx: Context<UpdateVaultSettings>,
+        guardian: Pubkey,
+        threshold: u64,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let vault = &mut ctx.accounts.vault;
 
-    #[account(
-        mut,
-        seeds = [b"vault", user.key().as_ref()],
-        bump = vault.bump,
-        constraint = vault.owner == user.key(),
-    )]
-    pub vault: Account<'info, CollateralVault>,
+        let effective_at = vault.set_guardian(guardian, threshold, now)?;
 
-    #[account(mut)]
-    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+        emit!(VaultGuardianUpdatedEvent {
+            user: ctx.accounts.user.key(),
+            vault: vault.key(),
+            guardian,
+            cosign_threshold: threshold,
+            effective_at,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Owner caps how much can leave the vault per rolling window (0 = no limit).
+    /// Lowering applies immediately; raising waits out a delay.
+    pub fn set_withdrawal_limit(
+        ctx: Context<UpdateVaultSettings>,
+        limit: u64,
+        window: i64,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let vault = &mut ctx.accounts.vault;
+
+        let effective_at = vault.set_withdrawal_limit(limit, window, now)?;
+
+        emit!(WithdrawalLimitUpdatedEvent {
+            user: ctx.accounts.user.key(),
+            vault: vault.key(),
+            limit,
+            window,
+            effective_at,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Last-resort exit while emergency mode is active: pays the owner the whole
+    /// `total_balance`, locked, pending and sub-account amounts included. Ignores
+    /// pauses, cooldowns, risk blocks, the withdrawal limit and the vault
+    /// guardian, as a lost guardian key or a low limit must not trap the funds
+    /// and only the owner's signature can trigger the exit. Starts a new vault
+    /// epoch, so every outstanding `LockRecord` and `SubAccount` goes
+    /// stale: none can be unlocked, settled or liquidated against the vault
+    /// again, and stale locks can be released (closed) without expiry.
+    pub fn emergency_withdraw(ctx: Context<EmergencyWithdraw>) -> Result<()> {
+        let vault_key = ctx.accounts.vault.key();
+        let user_key = ctx.accounts.user.key();
+        let now = Clock::get()?.unix_timestamp;
+
+        require!(
+            ctx.accounts.global_config.is_emergency(now),
+            VaultError::EmergencyModeInactive
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        let amount = vault.total_balance;
+        let locked_released = vault.locked_balance;
+
+        require!(amount > 0, VaultError::InvalidAmount);
+
+        let (debited, received) = pay_out_of_vault(
+            vault,
+            &mut ctx.accounts.vault_token_account,
+            &mut ctx.accounts.user_token_account,
+            &ctx.accounts.mint,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+
+        vault.book_emergency_withdrawal(debited)?;
+
+        // Nobody may be around to run `reconcile_tvl`; don't revert on drift
+        ctx.accounts.tvl_counter.sub_saturating(debited);
+
+        emit!(EmergencyWithdrawEvent {
+            user: user_key,
+            vault: vault_key,
+            amount: debited,
+            fee: debited.saturating_sub(received),
+            locked_released,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Closes an empty vault, its token account and withdraw allowlist,
+    /// refunding all rent to the owner. Recorded bad debt must be covered or
+    /// socialized, and lock records, sub-accounts and delegates closed, first.
+    pub fn close_vault(ctx: Context<CloseVault>) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+
+        vault.check_closable()?;
+        require!(
+            !vault.allowlist_enabled || ctx.accounts.withdraw_allowlist.is_some(),
+            VaultError::AllowlistRequired
+        );
+
+        let seeds = &[
+            b"vault",
+            vault.owner.as_ref(),
+            vault.mint.as_ref(),
+            &[vault.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        // Tokens sent straight to the ATA are not on the ledger; hand them back
+        let swept_amount = ctx.accounts.vault_token_account.amount;
+        if swept_amount > 0 {
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.vault_token_account.to_account_info(),
+                        to: ctx.accounts.user_token_account.to_account_info(),
+                        authority: vault.to_account_info(),
+                        mint: ctx.accounts.mint.to_account_info(),
+                    },
+                    signer,
+                ),
+                swept_amount,
+                ctx.accounts.mint.decimals,
+            )?;
+        }
+
+        // Token-2022 refuses to close an account holding withheld transfer
+        // fees; harvesting them to the mint is permissionless
+        let harvested_fees = withheld_transfer_fees(&ctx.accounts.vault_token_account.to_account_info())?;
+        if harvested_fees > 0 {
+            harvest_withheld_tokens_to_mint(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    HarvestWithheldTokensToMint {
+                        token_program_id: ctx.accounts.token_program.to_account_info(),
+                        mint: ctx.accounts.mint.to_account_info(),
+                    },
+                ),
+                vec![ctx.accounts.vault_token_account.to_account_info()],
+            )?;
+        }
+
+        token_interface::close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.vault_token_account.to_account_info(),
+                destination: ctx.accounts.user.to_account_info(),
+                authority: vault.to_account_info(),
+            },
+            signer,
+        ))?;
+
+        // The vault and allowlist accounts are closed to `user` by their `close` constraints
+        emit!(VaultClosedEvent {
+            user: ctx.accounts.user.key(),
+            vault: vault.key(),
+            mint: vault.mint,
+            swept_amount,
+            harvested_fees,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Moves a legacy vault (`LegacyCollateralVault` at `["vault", owner]`) to
+    /// the per-mint vault at `["vault", owner, mint]`: tokens, balances and
+    /// lifetime totals come along, then the legacy vault and its token account
+    /// are closed to the owner. Legacy locks name no position, so they move
+    /// into a `LockRecord` under `LockRecord::LEGACY_CALLER` that anyone can
+    /// release after `LegacyCollateralVault::LOCK_GRACE_PERIOD`.
+    pub fn migrate_vault(ctx: Context<MigrateVault>) -> Result<()> {
+        ensure_supported_mint(&ctx.accounts.mint.to_account_info())?;
+
+        let legacy = {
+            let data = ctx.accounts.legacy_vault.try_borrow_data()?;
+            LegacyCollateralVault::try_from_account_data(&data)?
+        };
+        require_keys_eq!(
+            legacy.token_account,
+            ctx.accounts.legacy_token_account.key(),
+            VaultError::InvalidVaultAccount
+        );
+        require!(
+            ctx.accounts.lock_record.is_some() == (legacy.locked_balance > 0),
+            VaultError::LockRecordRequired
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        let user_key = ctx.accounts.user.key();
+        let legacy_key = ctx.accounts.legacy_vault.key();
+
+        associated_token::create(
+            CpiContext::new(
+                ctx.accounts.associated_token_program.to_account_info(),
+                associated_token::Create {
+                    payer: ctx.accounts.user.to_account_info(),
+                    associated_token: ctx.accounts.vault_token_account.to_account_info(),
+                    authority: ctx.accounts.vault.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                },
+            ),
+        )?;
+
+        let legacy_seeds = &[b"vault", user_key.as_ref(), &[ctx.bumps.legacy_vault]];
+        let legacy_signer = &[&legacy_seeds[..]];
+
+        // The whole token account moves; anything beyond the ledger stays untracked
+        let moved = ctx.accounts.legacy_token_account.amount;
+        if moved > 0 {
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.legacy_token_account.to_account_info(),
+                        to: ctx.accounts.vault_token_account.to_account_info(),
+                        authority: ctx.accounts.legacy_vault.to_account_info(),
+                        mint: ctx.accounts.mint.to_account_info(),
+                    },
+                    legacy_signer,
+                ),
+                moved,
+                ctx.accounts.mint.decimals,
+            )?;
+        }
+
+        token_interface::close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.legacy_token_account.to_account_info(),
+                destination: ctx.accounts.user.to_account_info(),
+                authority: ctx.accounts.legacy_vault.to_account_info(),
+            },
+            legacy_signer,
+        ))?;
+
+        let received = {
+            let data = ctx.accounts.vault_token_account.try_borrow_data()?;
+            TokenAccount::try_deserialize(&mut &data[..])?.amount
+        };
+        let (available, locked) = legacy.carried_balances(received)?;
+        let total = available
+            .checked_add(locked)
+            .ok_or(VaultError::MathOverflow)?;
+
+        let vault_key = ctx.accounts.vault.key();
+        let vault = &mut ctx.accounts.vault;
+        vault.owner = user_key;
+        vault.mint = ctx.accounts.mint.key();
+        vault.token_account = ctx.accounts.vault_token_account.key();
+        vault.total_balance = total;
+        vault.locked_balance = locked;
+        vault.available_balance = available;
+        vault.total_deposited = legacy.total_deposited;
+        vault.total_withdrawn = legacy.total_withdrawn;
+        vault.created_at = legacy.created_at;
+        vault.bump = ctx.bumps.vault;
+        vault.version = CollateralVault::CURRENT_VERSION;
+        vault.bad_debt = 0;
+        vault.pending_withdrawal = 0;
+        vault.withdrawal_claimable_at = 0;
+        vault.withdrawal_blocked = false;
+        vault.withdrawal_limit = 0;
+        vault.withdrawal_window = 0;
+        vault.window_start = 0;
+        vault.withdrawn_in_window = 0;
+        vault.pending_withdrawal_limit = 0;
+        vault.pending_withdrawal_window = 0;
+        vault.pending_limit_effective_at = 0;
+        vault.allowlist_enabled = false;
+        vault.guardian = Pubkey::default();
+        vault.cosign_threshold = 0;
+        vault.pending_guardian = Pubkey::default();
+        vault.pending_cosign_threshold = 0;
+        vault.guardian_effective_at = 0;
+        vault.sub_account_balance = 0;
+        vault.epoch = 0;
+        vault.lock_count = 0;
+        vault.sub_account_count = 0;
+        vault.delegate_count = 0;
+        vault.pending_in_window = 0;
+        vault.withdrawn_in_prev_window = 0;
+        vault.pending_in_prev_window = 0;
+        vault.reserved = [0; 42];
+
+        if let Some(lock_record) = ctx.accounts.lock_record.as_mut() {
+            lock_record.vault = vault_key;
+            lock_record.caller_program = LockRecord::LEGACY_CALLER;
+            lock_record.position_id = LockRecord::LEGACY_POSITION_ID;
+            lock_record.amount = locked;
+            lock_record.rent_payer = user_key;
+            lock_record.created_at = now;
+            lock_record.expires_at = now
+                .checked_add(LegacyCollateralVault::LOCK_GRACE_PERIOD)
+                .ok_or(VaultError::MathOverflow)?;
+            lock_record.bump = ctx.bumps.lock_record.ok_or(VaultError::LockRecordRequired)?;
+            lock_record.epoch = 0;
+            vault.lock_count = 1;
+        }
+
+        let tvl = &mut ctx.accounts.tvl_counter;
+        tvl.mint = ctx.accounts.mint.key();
+        tvl.bump = ctx.bumps.tvl_counter;
+        tvl.add(total)?;
+
+        // Close the legacy vault to its owner
+        let legacy_ai = ctx.accounts.legacy_vault.to_account_info();
+        let user_ai = ctx.accounts.user.to_account_info();
+        let legacy_lamports = legacy_ai.lamports();
+        **user_ai.try_borrow_mut_lamports()? = user_ai
+            .lamports()
+            .checked_add(legacy_lamports)
+            .ok_or(VaultError::MathOverflow)?;
+        **legacy_ai.try_borrow_mut_lamports()? = 0;
+        legacy_ai.assign(&system_program::ID);
+        legacy_ai.resize(0)?;
+
+        emit!(VaultMigratedEvent {
+            legacy_vault: legacy_key,
+            vault: vault_key,
+            from_version: 0,
+            to_version: CollateralVault::CURRENT_VERSION,
+            total_balance: total,
+            locked_balance: locked,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    pub fn initialize_vault_authority(
+        ctx: Context<InitializeVaultAuthority>,
+        authorized_programs: Vec<Pubkey>,
+    ) -> Result<()> {
+        let admin_key = ctx.accounts.admin.key();
+        let now = Clock::get()?.unix_timestamp;
+
+        let authority = &mut ctx.accounts.vault_authority;
+        authority.admin = admin_key;
+        authority.pending_admin = None;
+        authority.authorized_programs = Vec::with_capacity(authorized_programs.len());
+        authority.bump = ctx.bumps.vault_authority;
+
+        for program in authorized_programs {
+            authority.add_program(program)?;
+        }
+
+        emit!(VaultAuthorityInitializedEvent {
+            admin: admin_key,
+            authorized_programs: authority.authorized_programs.clone(),
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Moves a whitelist created before `admin` existed to the current
+    /// layout: the account is grown to `VaultAuthority::LEN` (the admin tops
+    /// up its rent) and the signing upgrade authority becomes its admin.
+    pub fn migrate_vault_authority(ctx: Context<MigrateVaultAuthority>) -> Result<()> {
+        let legacy = {
+            let data = ctx.accounts.vault_authority.try_borrow_data()?;
+            LegacyVaultAuthority::try_from_account_data(&data)?
+        };
+
+        let admin_key = ctx.accounts.admin.key();
+        let authority_ai = ctx.accounts.vault_authority.to_account_info();
+
+        let rent = Rent::get()?.minimum_balance(VaultAuthority::LEN);
+        let top_up = rent.saturating_sub(authority_ai.lamports());
+        if top_up > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.admin.to_account_info(),
+                        to: authority_ai.clone(),
+                    },
+                ),
+                top_up,
+            )?;
+        }
+        authority_ai.resize(VaultAuthority::LEN)?;
+
+        let authority = VaultAuthority {
+            admin: admin_key,
+            pending_admin: None,
+            authorized_programs: legacy.authorized_programs,
+            bump: ctx.bumps.vault_authority,
+        };
+        {
+            let mut data = authority_ai.try_borrow_mut_data()?;
+            let mut writer: &mut [u8] = &mut data;
+            authority.try_serialize(&mut writer)?;
+        }
+
+        emit!(VaultAuthorityMigratedEvent {
+            admin: admin_key,
+            authorized_programs: authority.authorized_programs,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn add_authorized_program(
+        ctx: Context<UpdateVaultAuthority>,
+        program: Pubkey,
+    ) -> Result<()> {
+        let authority = &mut ctx.accounts.vault_authority;
+        authority.add_program(program)?;
+
+        emit!(AuthorizedProgramAddedEvent {
+            admin: authority.admin,
+            program,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn remove_authorized_program(
+        ctx: Context<UpdateVaultAuthority>,
+        program: Pubkey,
+    ) -> Result<()> {
+        let authority = &mut ctx.accounts.vault_authority;
+        authority.remove_program(&program)?;
+
+        emit!(AuthorizedProgramRemovedEvent {
+            admin: authority.admin,
+            program,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Step 1 of the admin handover. The new admin must call `accept_admin`.
+    pub fn propose_admin(ctx: Context<UpdateVaultAuthority>, new_admin: Pubkey) -> Result<()> {
+        let authority = &mut ctx.accounts.vault_authority;
+        authority.pending_admin = Some(new_admin);
+
+        emit!(AdminProposedEvent {
+            admin: authority.admin,
+            pending_admin: new_admin,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Step 2 of the admin handover, signed by the proposed admin.
+    pub fn accept_admin(ctx: Context<AcceptAdmin>) -> Result<()> {
+        let authority = &mut ctx.accounts.vault_authority;
+        let previous_admin = authority.admin;
+
+        authority.admin = ctx.accounts.new_admin.key();
+        authority.pending_admin = None;
+
+        emit!(AdminTransferredEvent {
+            previous_admin,
+            new_admin: authority.admin,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn initialize_global_config(
+        ctx: Context<InitializeGlobalConfig>,
+        guardian: Pubkey,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.global_config;
+        config.guardian = guardian;
+        config.paused = 0;
+        config.withdrawal_cooldown = 0;
+        config.max_deposit_per_tx = 0;
+        config.max_vault_balance = 0;
+        config.max_tvl = 0;
+        config.emergency_mode = false;
+        config.emergency_inactivity_period = 0;
+        config.last_authorized_activity = Clock::get()?.unix_timestamp;
+        config.bump = ctx.bumps.global_config;
+
+        emit!(GlobalConfigInitializedEvent {
+            admin: ctx.accounts.admin.key(),
+            guardian,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_guardian(ctx: Context<UpdateGlobalConfig>, guardian: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.global_config;
+        let previous_guardian = config.guardian;
+        config.guardian = guardian;
+
+        emit!(GuardianUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            previous_guardian,
+            new_guardian: guardian,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Sets the request-to-claim delay. Zero turns the two-step flow off and
+    /// re-enables instant `withdraw`.
+    pub fn set_withdrawal_cooldown(
+        ctx: Context<UpdateGlobalConfig>,
+        cooldown: i64,
+    ) -> Result<()> {
+        require!(cooldown >= 0, VaultError::InvalidCooldown);
+
+        let config = &mut ctx.accounts.global_config;
+        let previous_cooldown = config.withdrawal_cooldown;
+        config.withdrawal_cooldown = cooldown;
+
+        emit!(WithdrawalCooldownUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            previous_cooldown,
+            new_cooldown: cooldown,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Updates the deposit limits. Zero disables a cap.
+    pub fn set_deposit_caps(
+        ctx: Context<UpdateGlobalConfig>,
+        max_deposit_per_tx: u64,
+        max_vault_balance: u64,
+        max_tvl: u64,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.global_config;
+        config.max_deposit_per_tx = max_deposit_per_tx;
+        config.max_vault_balance = max_vault_balance;
+        config.max_tvl = max_tvl;
+
+        emit!(DepositCapsUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            max_deposit_per_tx,
+            max_vault_balance,
+            max_tvl,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Admin sets a mint's TVL counter to the audited sum of its vault
+    /// balances. Allowed once per mint, for balances the counter missed
+    /// before every token path updated it.
+    pub fn reconcile_tvl(ctx: Context<ReconcileTvl>, total_value_locked: u64) -> Result<()> {
+        let tvl = &mut ctx.accounts.tvl_counter;
+        require!(!tvl.reconciled, VaultError::TvlAlreadyReconciled);
+
+        let previous = tvl.total_value_locked;
+        tvl.mint = ctx.accounts.mint.key();
+        tvl.bump = ctx.bumps.tvl_counter;
+        tvl.total_value_locked = total_value_locked;
+        tvl.reconciled = true;
+
+        emit!(TvlReconciledEvent {
+            admin: ctx.accounts.admin.key(),
+            mint: tvl.mint,
+            previous,
+            total_value_locked,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Turns emergency mode on or off and sets the inactivity period after
+    /// which it turns on by itself (0 = never).
+    pub fn set_emergency_mode(
+        ctx: Context<UpdateGlobalConfig>,
+        emergency_mode: bool,
+        inactivity_period: i64,
+    ) -> Result<()> {
+        require!(inactivity_period >= 0, VaultError::InvalidInactivityPeriod);
+
+        let config = &mut ctx.accounts.global_config;
+        config.emergency_mode = emergency_mode;
+        config.emergency_inactivity_period = inactivity_period;
+
+        emit!(EmergencyModeUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            emergency_mode,
+            inactivity_period,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Pause (`paused = true`) or resume the instruction types in `flags`.
+    /// The admin or the guardian can pause; only the admin can resume.
+    pub fn set_paused(ctx: Context<SetPaused>, flags: u32, paused: bool) -> Result<()> {
+        require!(
+            flags != 0 && flags & !GlobalConfig::PAUSE_ALL == 0,
+            VaultError::InvalidPauseFlags
+        );
+
+        let config = &mut ctx.accounts.global_config;
+        let authority = ctx.accounts.authority.key();
+        let now = Clock::get()?.unix_timestamp;
+
+        // The guardian can only pull the brake; resuming is the admin's call
+        require!(
+            paused || authority == ctx.accounts.vault_authority.admin,
+            VaultError::Unauthorized
+        );
+
+        if paused {
+            config.paused |= flags;
+
+            emit!(PausedEvent {
+                authority,
+                flags,
+                paused: config.paused,
+                timestamp: now,
+            });
+        } else {
+            config.paused &= !flags;
+
+            emit!(UnpausedEvent {
+                authority,
+                flags,
+                paused: config.paused,
+                timestamp: now,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// `expires_at` (optional) lets anyone release the lock after that time;
+    /// passing it again on a top-up moves the expiry.
+    pub fn lock_collateral(
+        ctx: Context<LockCollateral>,
+        position_id: u64,
+        amount: u64,
+        expires_at: Option<i64>,
+    ) -> Result<()> {
+        require!(amount > 0, VaultError::InvalidAmount);
+
+        let vault = &mut ctx.accounts.vault;
+        let lock_record = &mut ctx.accounts.lock_record;
+        let caller_key = ctx.accounts.caller_program.key();
+        let vault_key = vault.key();
+        let now = Clock::get()?.unix_timestamp;
+
+        ctx.accounts.global_config.record_authorized_activity(now);
+
+        require!(
+            vault.available_balance >= amount,
+            VaultError::InsufficientAvailableBalance
+        );
+
+        // Fresh record (init_if_needed leaves it zeroed)
+        if lock_record.vault == Pubkey::default() {
+            lock_record.vault = vault_key;
+            lock_record.caller_program = caller_key;
+            lock_record.position_id = position_id;
+            lock_record.amount = 0;
+            lock_record.rent_payer = ctx.accounts.payer.key();
+            lock_record.created_at = now;
+            lock_record.expires_at = 0;
+            lock_record.bump = ctx.bumps.lock_record;
+            lock_record.epoch = vault.epoch;
+            vault.lock_count = vault
+                .lock_count
+                .checked_add(1)
+                .ok_or(VaultError::MathOverflow)?;
+        }
+
+        // An emergency withdrawal already paid out whatever the record held
+        if lock_record.epoch != vault.epoch {
+            lock_record.amount = 0;
+            lock_record.created_at = now;
+            lock_record.expires_at = 0;
+            lock_record.epoch = vault.epoch;
+        }
+
+        if let Some(expires_at) = expires_at {
+            require!(expires_at > now, VaultError::InvalidExpiry);
+            lock_record.expires_at = expires_at;
+        }
+
+        lock_record.amount = lock_record
+            .amount
+            .checked_add(amount)
+            .ok_or(VaultError::MathOverflow)?;
+
+        vault.available_balance = vault
+            .available_balance
+            .checked_sub(amount)
+            .ok_or(VaultError::MathOverflow)?;
+
+        vault.locked_balance = vault
+            .locked_balance
+            .checked_add(amount)
+            .ok_or(VaultError::MathOverflow)?;
+
+        emit!(LockEvent {
+            vault: vault_key,
+            sub_account: None,
+            caller: caller_key,
+            position_id,
+            amount,
+            position_locked: lock_record.amount,
+            new_locked_balance: vault.locked_balance,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    pub fn unlock_collateral(
+        ctx: Context<UnlockCollateral>,
+        position_id: u64,
+        amount: u64,
+    ) -> Result<()> {
+        require!(amount > 0, VaultError::InvalidAmount);
+
+        let vault = &mut ctx.accounts.vault;
+        let lock_record = &mut ctx.accounts.lock_record;
+        let caller_key = ctx.accounts.caller_program.key();
+        let vault_key = vault.key();
+        let now = Clock::get()?.unix_timestamp;
+
+        ctx.accounts.global_config.record_authorized_activity(now);
+
+        vault.require_current_lock(lock_record)?;
+
+        require!(
+            lock_record.amount >= amount,
+            VaultError::InsufficientLockedBalance
+        );
+
+        require!(
+            vault.locked_balance >= amount,
+            VaultError::InsufficientLockedBalance
+        );
+
+        lock_record.amount = lock_record
+            .amount
+            .checked_sub(amount)
+            .ok_or(VaultError::MathOverflow)?;
+
+        vault.locked_balance = vault
+            .locked_balance
+            .checked_sub(amount)
+            .ok_or(VaultError::MathOverflow)?;
+
+        vault.available_balance = vault
+            .available_balance
+            .checked_add(amount)
+            .ok_or(VaultError::MathOverflow)?;
+
+        emit!(UnlockEvent {
+            vault: vault_key,
+            sub_account: None,
+            caller: caller_key,
+            position_id,
+            amount,
+            position_locked: lock_record.amount,
+            new_locked_balance: vault.locked_balance,
+            expired: false,
+            timestamp: now,
+        });
+
+        // Position fully released: return the record's rent
+        if lock_record.amount == 0 {
+            vault.untrack_lock(None)?;
+            lock_record.close(ctx.accounts.rent_receiver.to_account_info())?;
+        }
+
+        Ok(())
+    }
+
+    /// Permissionless: returns an expired lock's collateral to the owner's
+    /// available balance, so a broken integration cannot strand funds. It
+    /// ignores pauses for the same reason. A stale lock (older vault epoch)
+    /// is closed at once and moves nothing.
+    pub fn release_expired_lock(ctx: Context<ReleaseExpiredLock>) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        let lock_record = &mut ctx.accounts.lock_record;
+        let now = Clock::get()?.unix_timestamp;
+
+        let amount = if lock_record.epoch == vault.epoch {
+            require!(lock_record.is_expired(now), VaultError::LockNotExpired);
+            lock_record.amount
+        } else {
+            0
+        };
+
+        vault.locked_balance = vault
+            .locked_balance
+            .checked_sub(amount)
+            .ok_or(VaultError::MathOverflow)?;
+
+        vault.available_balance = vault
+            .available_balance
+            .checked_add(amount)
+            .ok_or(VaultError::MathOverflow)?;
+
+        lock_record.amount = 0;
+
+        emit!(UnlockEvent {
+            vault: vault.key(),
+            sub_account: None,
+            caller: lock_record.caller_program,
+            position_id: lock_record.position_id,
+            amount,
+            position_locked: 0,
+            new_locked_balance: vault.locked_balance,
+            expired: true,
+            timestamp: now,
+        });
+
+        vault.untrack_lock(None)?;
+        lock_record.close(ctx.accounts.rent_receiver.to_account_info())?;
+
+        Ok(())
+    }
+
+    pub fn create_sub_account(ctx: Context<CreateSubAccount>, index: u16) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let sub_account = &mut ctx.accounts.sub_account;
+
+        sub_account.vault = ctx.accounts.vault.key();
+        sub_account.index = index;
+        sub_account.total_balance = 0;
+        sub_account.locked_balance = 0;
+        sub_account.available_balance = 0;
+        sub_account.created_at = now;
+        sub_account.bump = ctx.bumps.sub_account;
+        sub_account.epoch = ctx.accounts.vault.epoch;
+        sub_account.lock_count = 0;
+
+        let vault = &mut ctx.accounts.vault;
+        vault.sub_account_count = vault
+            .sub_account_count
+            .checked_add(1)
+            .ok_or(VaultError::MathOverflow)?;
+
+        emit!(SubAccountCreatedEvent {
+            user: ctx.accounts.user.key(),
+            vault: sub_account.vault,
+            sub_account: sub_account.key(),
+            index,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Closes a sub-account with no locks, refunding its rent to the owner.
+    /// It must be empty, or stale: an emergency withdrawal already paid out
+    /// what it held.
+    pub fn close_sub_account(ctx: Context<CloseSubAccount>) -> Result<()> {
+        let sub_account = &ctx.accounts.sub_account;
+        let stale = ctx.accounts.vault.require_current_sub_account(sub_account).is_err();
+
+        require!(
+            sub_account.lock_count == 0 && (sub_account.total_balance == 0 || stale),
+            VaultError::SubAccountNotEmpty
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        vault.sub_account_count = vault
+            .sub_account_count
+            .checked_sub(1)
+            .ok_or(VaultError::MathOverflow)?;
+
+        // The sub-account itself is closed to `user` by the `close` constraint
+        emit!(SubAccountClosedEvent {
+            user: ctx.accounts.user.key(),
+            vault: vault.key(),
+            sub_account: sub_account.key(),
+            index: sub_account.index,
+            stale,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Moves available collateral between the vault and its sub-accounts.
+    /// Omitting `from_sub_account` / `to_sub_account` means the vault itself.
+    pub fn move_sub_account_funds(ctx: Context<MoveSubAccountFunds>, amount: u64) -> Result<()> {
+        require!(amount > 0, VaultError::InvalidAmount);
+
+        let from_key = ctx.accounts.from_sub_account.as_ref().map(|a| a.key());
+        let to_key = ctx.accounts.to_sub_account.as_ref().map(|a| a.key());
+        require!(from_key != to_key, VaultError::SameSubAccount);
+
+        let vault = &mut ctx.accounts.vault;
+
+        let from_index = match ctx.accounts.from_sub_account.as_mut() {
+            Some(from) => {
+                vault.require_current_sub_account(from)?;
+                require!(
+                    from.available_balance >= amount,
+                    VaultError::InsufficientAvailableBalance
+                );
+                from.available_balance = from
+                    .available_balance
+                    .checked_sub(amount)
+                    .ok_or(VaultError::MathOverflow)?;
+                from.total_balance = from
+                    .total_balance
+                    .checked_sub(amount)
+                    .ok_or(VaultError::MathOverflow)?;
+                vault.sub_account_balance = vault
+                    .sub_account_balance
+                    .checked_sub(amount)
+                    .ok_or(VaultError::MathOverflow)?;
+                Some(from.index)
+            }
+            None => {
+                require!(
+                    vault.available_balance >= amount,
+                    VaultError::InsufficientAvailableBalance
+                );
+                vault.available_balance = vault
+                    .available_balance
+                    .checked_sub(amount)
+                    .ok_or(VaultError::MathOverflow)?;
+                None
+            }
+        };
+
+        let to_index = match ctx.accounts.to_sub_account.as_mut() {
+            Some(to) => {
+                vault.require_current_sub_account(to)?;
+                to.available_balance = to
+                    .available_balance
+                    .checked_add(amount)
+                    .ok_or(VaultError::MathOverflow)?;
+                to.total_balance = to
+                    .total_balance
+                    .checked_add(amount)
+                    .ok_or(VaultError::MathOverflow)?;
+                vault.sub_account_balance = vault
+                    .sub_account_balance
+                    .checked_add(amount)
+                    .ok_or(VaultError::MathOverflow)?;
+                Some(to.index)
+            }
+            None => {
+                vault.available_balance = vault
+                    .available_balance
+                    .checked_add(amount)
+                    .ok_or(VaultError::MathOverflow)?;
+                None
+            }
+        };
+
+        emit!(SubAccountTransferEvent {
+            user: ctx.accounts.user.key(),
+            vault: vault.key(),
+            from_index,
+            to_index,
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// `lock_collateral` against one sub-account's available balance.
+    pub fn lock_sub_account_collateral(
+        ctx: Context<LockSubAccountCollateral>,
+        position_id: u64,
+        amount: u64,
+        expires_at: Option<i64>,
+    ) -> Result<()> {
+        require!(amount > 0, VaultError::InvalidAmount);
+
+        let sub_account = &mut ctx.accounts.sub_account;
+        let lock_record = &mut ctx.accounts.lock_record;
+        let caller_key = ctx.accounts.caller_program.key();
+        let sub_account_key = sub_account.key();
+        let now = Clock::get()?.unix_timestamp;
+
+        ctx.accounts.global_config.record_authorized_activity(now);
+
+        let vault = &mut ctx.accounts.vault;
+        vault.require_current_sub_account(sub_account)?;
+
+        require!(
+            sub_account.available_balance >= amount,
+            VaultError::InsufficientAvailableBalance
+        );
+
+        // Fresh record (init_if_needed leaves it zeroed)
+        if lock_record.vault == Pubkey::default() {
+            lock_record.vault = sub_account_key;
+            lock_record.caller_program = caller_key;
+            lock_record.position_id = position_id;
+            lock_record.amount = 0;
+            lock_record.rent_payer = ctx.accounts.payer.key();
+            lock_record.created_at = now;
+            lock_record.expires_at = 0;
+            lock_record.bump = ctx.bumps.lock_record;
+            lock_record.epoch = vault.epoch;
+            sub_account.lock_count = sub_account
+                .lock_count
+                .checked_add(1)
+                .ok_or(VaultError::MathOverflow)?;
+            vault.lock_count = vault
+                .lock_count
+                .checked_add(1)
+                .ok_or(VaultError::MathOverflow)?;
+        }
+
+        // A current sub-account never holds a stale lock: stale sub-accounts
+        // must be closed and recreated before locking again
+        vault.require_current_lock(lock_record)?;
+
+        if let Some(expires_at) = expires_at {
+            require!(expires_at > now, VaultError::InvalidExpiry);
+            lock_record.expires_at = expires_at;
+        }
+
+        lock_record.amount = lock_record
+            .amount
+            .checked_add(amount)
+            .ok_or(VaultError::MathOverflow)?;
+
+        sub_account.available_balance = sub_account
+            .available_balance
+            .checked_sub(amount)
+            .ok_or(VaultError::MathOverflow)?;
+
+        sub_account.locked_balance = sub_account
+            .locked_balance
+            .checked_add(amount)
+            .ok_or(VaultError::MathOverflow)?;
+
+        emit!(LockEvent {
+            vault: sub_account.vault,
+            sub_account: Some(sub_account.index),
+            caller: caller_key,
+            position_id,
+            amount,
+            position_locked: lock_record.amount,
+            new_locked_balance: sub_account.locked_balance,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// `unlock_collateral` for a sub-account lock.
+    pub fn unlock_sub_account_collateral(
+        ctx: Context<UnlockSubAccountCollateral>,
+        position_id: u64,
+        amount: u64,
+    ) -> Result<()> {
+        require!(amount > 0, VaultError::InvalidAmount);
+
+        let sub_account = &mut ctx.accounts.sub_account;
+        let lock_record = &mut ctx.accounts.lock_record;
+        let caller_key = ctx.accounts.caller_program.key();
+        let now = Clock::get()?.unix_timestamp;
+
+        ctx.accounts.global_config.record_authorized_activity(now);
+
+        ctx.accounts.vault.require_current_lock(lock_record)?;
+
+        require!(
+            lock_record.amount >= amount && sub_account.locked_balance >= amount,
+            VaultError::InsufficientLockedBalance
+        );
+
+        lock_record.amount = lock_record
+            .amount
+            .checked_sub(amount)
+            .ok_or(VaultError::MathOverflow)?;
+
+        sub_account.locked_balance = sub_account
+            .locked_balance
+            .checked_sub(amount)
+            .ok_or(VaultError::MathOverflow)?;
+
+        sub_account.available_balance = sub_account
+            .available_balance
+            .checked_add(amount)
+            .ok_or(VaultError::MathOverflow)?;
+
+        emit!(UnlockEvent {
+            vault: sub_account.vault,
+            sub_account: Some(sub_account.index),
+            caller: caller_key,
+            position_id,
+            amount,
+            position_locked: lock_record.amount,
+            new_locked_balance: sub_account.locked_balance,
+            expired: false,
+            timestamp: now,
+        });
+
+        // Position fully released: return the record's rent
+        if lock_record.amount == 0 {
+            ctx.accounts.vault.untrack_lock(Some(sub_account))?;
+            lock_record.close(ctx.accounts.rent_receiver.to_account_info())?;
+        }
+
+        Ok(())
+    }
+
+    /// `release_expired_lock` for a sub-account lock. Permissionless and
+    /// not pausable.
+    pub fn release_expired_sub_account_lock(
+        ctx: Context<ReleaseExpiredSubAccountLock>,
+    ) -> Result<()> {
+        let sub_account = &mut ctx.accounts.sub_account;
+        let lock_record = &mut ctx.accounts.lock_record;
+        let now = Clock::get()?.unix_timestamp;
+
+        let amount = if lock_record.epoch == ctx.accounts.vault.epoch {
+            require!(lock_record.is_expired(now), VaultError::LockNotExpired);
+            lock_record.amount
+        } else {
+            0
+        };
+
+        sub_account.locked_balance = sub_account
+            .locked_balance
+            .checked_sub(amount)
+            .ok_or(VaultError::MathOverflow)?;
+
+        sub_account.available_balance = sub_account
+            .available_balance
+            .checked_add(amount)
+            .ok_or(VaultError::MathOverflow)?;
+
+        lock_record.amount = 0;
+
+        emit!(UnlockEvent {
+            vault: sub_account.vault,
+            sub_account: Some(sub_account.index),
+            caller: lock_record.caller_program,
+            position_id: lock_record.position_id,
+            amount,
+            position_locked: 0,
+            new_locked_balance: sub_account.locked_balance,
+            expired: true,
+            timestamp: now,
+        });
+
+        ctx.accounts.vault.untrack_lock(Some(sub_account))?;
+        lock_record.close(ctx.accounts.rent_receiver.to_account_info())?;
+
+        Ok(())
+    }
+
+    pub fn transfer_collateral(
+        ctx: Context<TransferCollateral>,
+        amount: u64,
+        mode: TransferMode,
+    ) -> Result<()> {
+        require!(amount > 0, VaultError::InvalidAmount);
+
+        let from_vault_ai = ctx.accounts.from_vault.to_account_info();
+        let from_vault = &mut ctx.accounts.from_vault;
+        let to_vault = &mut ctx.accounts.to_vault;
+        let now = Clock::get()?.unix_timestamp;
+
+        ctx.accounts.global_config.record_authorized_activity(now);
+
+        let position_id = from_vault.debit_transfer(
+            mode,
+            amount,
+            ctx.accounts.lock_record.as_deref_mut(),
+            ctx.accounts.from_sub_account.as_deref_mut(),
+        )?;
+
+        // Move the tokens so both vault token accounts keep backing their ledgers
+        let seeds = &[
+            b"vault",
+            from_vault.owner.as_ref(),
+            from_vault.mint.as_ref(),
+            &[from_vault.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let to_balance_before = ctx.accounts.to_vault_token_account.amount;
+
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.from_vault_token_account.to_account_info(),
+                    to: ctx.accounts.to_vault_token_account.to_account_info(),
+                    authority: from_vault_ai,
+                    mint: ctx.accounts.mint.to_account_info(),
+                },
+                signer,
+            ),
+            amount,
+            ctx.accounts.mint.decimals,
+        )?;
+
+        // The receiver is credited what arrived (fee mints withhold part of it)
+        ctx.accounts.to_vault_token_account.reload()?;
+        let received = ctx
+            .accounts
+            .to_vault_token_account
+            .amount
+            .checked_sub(to_balance_before)
+            .ok_or(VaultError::MathOverflow)?;
+
+        from_vault.settle_transfer(to_vault, &mut ctx.accounts.tvl_counter, amount, received)?;
+
+        let (from_sub_account, from_available_balance, from_locked_balance) =
+            match &ctx.accounts.from_sub_account {
+                Some(sub) => (Some(sub.index), sub.available_balance, sub.locked_balance),
+                None => (None, from_vault.available_balance, from_vault.locked_balance),
+            };
+
+        emit!(TransferEvent {
+            from_vault: from_vault.key(),
+            from_sub_account,
+            to_vault: to_vault.key(),
+            amount,
+            fee: amount.saturating_sub(received),
+            mode,
+            position_id,
+            from_available_balance,
+            from_locked_balance,
+            timestamp: now,
+        });
+
+        // Position fully settled: return the record's rent
+        if let Some(lock_record) = &ctx.accounts.lock_record {
+            if mode == TransferMode::Locked && lock_record.amount == 0 {
+                let rent_receiver = ctx
+                    .accounts
+                    .rent_receiver
+                    .as_ref()
+                    .filter(|r| r.key() == lock_record.rent_payer)
+                    .ok_or(VaultError::InvalidRentReceiver)?;
+
+                ctx.accounts
+                    .from_vault
+                    .untrack_lock(ctx.accounts.from_sub_account.as_deref_mut())?;
+                lock_record.close(rent_receiver.to_account_info())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Seizes locked collateral from an underwater position: `liquidator_reward`
+    /// goes to the liquidator, `insurance_fee` to the insurance fund.
+    pub fn liquidate(
+        ctx: Context<Liquidate>,
+        position_id: u64,
+        liquidator_reward: u64,
+        insurance_fee: u64,
+    ) -> Result<()> {
+        let seized = liquidator_reward
+            .checked_add(insurance_fee)
+            .ok_or(VaultError::MathOverflow)?;
+        require!(seized > 0, VaultError::InvalidAmount);
+
+        let vault_ai = ctx.accounts.vault.to_account_info();
+        let vault = &mut ctx.accounts.vault;
+        let lock_record = &mut ctx.accounts.lock_record;
+        let now = Clock::get()?.unix_timestamp;
+
+        ctx.accounts.global_config.record_authorized_activity(now);
+
+        // The whole transaction reverts if a transfer below fails
+        vault.seize_locked(
+            lock_record,
+            ctx.accounts.sub_account.as_deref_mut(),
+            &mut ctx.accounts.tvl_counter,
+            seized,
+        )?;
+
+        let seeds = &[
+            b"vault",
+            vault.owner.as_ref(),
+            vault.mint.as_ref(),
+            &[vault.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        if liquidator_reward > 0 {
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.vault_token_account.to_account_info(),
+                        to: ctx.accounts.liquidator_token_account.to_account_info(),
+                        authority: vault_ai.clone(),
+                        mint: ctx.accounts.mint.to_account_info(),
+                    },
+                    signer,
+                ),
+                liquidator_reward,
+                ctx.accounts.mint.decimals,
+            )?;
+        }
+
+        if insurance_fee > 0 {
+            let fund_balance_before = ctx.accounts.insurance_fund_token_account.amount;
+
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.vault_token_account.to_account_info(),
+                        to: ctx.accounts.insurance_fund_token_account.to_account_info(),
+                        authority: vault_ai,
+                        mint: ctx.accounts.mint.to_account_info(),
+                    },
+                    signer,
+                ),
+                insurance_fee,
+                ctx.accounts.mint.decimals,
+            )?;
+
+            // Credit the fund what actually arrived
+            ctx.accounts.insurance_fund_token_account.reload()?;
+            let received = ctx
+                .accounts
+                .insurance_fund_token_account
+                .amount
+                .checked_sub(fund_balance_before)
+                .ok_or(VaultError::MathOverflow)?;
+
+            ctx.accounts.insurance_fund.credit(received)?;
+        }
+
+        emit!(LiquidationEvent {
+            vault: vault.key(),
+            sub_account: ctx.accounts.sub_account.as_ref().map(|s| s.index),
+            caller: ctx.accounts.caller_program.key(),
+            position_id,
+            liquidator: ctx.accounts.liquidator_token_account.owner,
+            liquidator_reward,
+            insurance_fee,
+            position_locked: lock_record.amount,
+            new_total_balance: vault.total_balance,
+            timestamp: now,
+        });
+
+        // Position fully seized: return the record's rent
+        if lock_record.amount == 0 {
+            vault.untrack_lock(ctx.accounts.sub_account.as_deref_mut())?;
+            lock_record.close(ctx.accounts.rent_receiver.to_account_info())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn initialize_insurance_fund(ctx: Context<InitializeInsuranceFund>) -> Result<()> {
+        ensure_supported_mint(&ctx.accounts.mint.to_account_info())?;
+
+        associated_token::create(
+            CpiContext::new(
+                ctx.accounts.associated_token_program.to_account_info(),
+                associated_token::Create {
+                    payer: ctx.accounts.admin.to_account_info(),
+                    associated_token: ctx.accounts.insurance_fund_token_account.to_account_info(),
+                    authority: ctx.accounts.insurance_fund.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                },
+            ),
+        )?;
+
+        let now = Clock::get()?.unix_timestamp;
+        let fund = &mut ctx.accounts.insurance_fund;
+
+        fund.mint = ctx.accounts.mint.key();
+        fund.token_account = ctx.accounts.insurance_fund_token_account.key();
+        fund.balance = 0;
+        fund.total_received = 0;
+        fund.total_drawn = 0;
+        fund.created_at = now;
+        fund.bump = ctx.bumps.insurance_fund;
+
+        emit!(InsuranceFundInitializedEvent {
+            admin: ctx.accounts.admin.key(),
+            insurance_fund: fund.key(),
+            mint: fund.mint,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Anyone can top up the insurance fund (e.g. protocol fee sweeps).
+    pub fn fund_insurance(ctx: Context<FundInsurance>, amount: u64) -> Result<()> {
+        require!(amount > 0, VaultError::InvalidAmount);
+
+        let balance_before = ctx.accounts.insurance_fund_token_account.amount;
+
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.funder_token_account.to_account_info(),
+                    to: ctx.accounts.insurance_fund_token_account.to_account_info(),
+                    authority: ctx.accounts.funder.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                },
+            ),
+            amount,
+            ctx.accounts.mint.decimals,
+        )?;
+
+        ctx.accounts.insurance_fund_token_account.reload()?;
+        let received = ctx
+            .accounts
+            .insurance_fund_token_account
+            .amount
+            .checked_sub(balance_before)
+            .ok_or(VaultError::MathOverflow)?;
+
+        let fund = &mut ctx.accounts.insurance_fund;
+        fund.credit(received)?;
+
+        emit!(InsuranceFundDepositEvent {
+            funder: ctx.accounts.funder.key(),
+            insurance_fund: fund.key(),
+            amount: received,
+            new_fund_balance: fund.balance,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Authorized program moves insurance collateral into a user vault,
+    /// e.g. to cover bad debt. The vault is credited as available collateral.
+    pub fn draw_insurance_fund(
+        ctx: Context<DrawInsuranceFund>,
+        amount: u64,
+        reason: InsuranceDrawReason,
+    ) -> Result<()> {
+        require!(amount > 0, VaultError::InvalidAmount);
+
+        let now = Clock::get()?.unix_timestamp;
+        ctx.accounts.global_config.record_authorized_activity(now);
+
+        let fund_ai = ctx.accounts.insurance_fund.to_account_info();
+        let fund = &mut ctx.accounts.insurance_fund;
+        let vault = &mut ctx.accounts.vault;
+
+        require!(fund.balance >= amount, VaultError::InsufficientInsuranceFund);
+
+        let seeds = &[
+            b"insurance_fund",
+            fund.mint.as_ref(),
+            &[fund.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let vault_balance_before = ctx.accounts.vault_token_account.amount;
+
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.insurance_fund_token_account.to_account_info(),
+                    to: ctx.accounts.vault_token_account.to_account_info(),
+                    authority: fund_ai,
+                    mint: ctx.accounts.mint.to_account_info(),
+                },
+                signer,
+            ),
+            amount,
+            ctx.accounts.mint.decimals,
+        )?;
+
+        ctx.accounts.vault_token_account.reload()?;
+        let received = ctx
+            .accounts
+            .vault_token_account
+            .amount
+            .checked_sub(vault_balance_before)
+            .ok_or(VaultError::MathOverflow)?;
+
+        fund.balance = fund
+            .balance
+            .checked_sub(amount)
+            .ok_or(VaultError::MathOverflow)?;
+        fund.total_drawn = fund
+            .total_drawn
+            .checked_add(amount)
+            .ok_or(VaultError::MathOverflow)?;
+
+        vault.total_balance = vault
+            .total_balance
+            .checked_add(received)
+            .ok_or(VaultError::MathOverflow)?;
+        vault.available_balance = vault
+            .available_balance
+            .checked_add(received)
+            .ok_or(VaultError::MathOverflow)?;
+
+        // Insurance money becomes user collateral
+        ctx.accounts.tvl_counter.add(received)?;
+
+        emit!(InsuranceDrawEvent {
+            insurance_fund: fund.key(),
+            vault: vault.key(),
+            caller: ctx.accounts.caller_program.key(),
+            amount,
+            reason,
+            new_fund_balance: fund.balance,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Authorized risk program holds (or releases) a vault's pending withdrawal,
+    /// e.g. while a liquidation of that account is in flight.
+    pub fn set_withdrawal_block(ctx: Context<SetWithdrawalBlock>, blocked: bool) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        ctx.accounts.global_config.record_authorized_activity(now);
+
+        let vault = &mut ctx.accounts.vault;
+        vault.withdrawal_blocked = blocked;
+
+        emit!(WithdrawalBlockSetEvent {
+            vault: vault.key(),
+            caller: ctx.accounts.caller_program.key(),
+            blocked,
+            pending_withdrawal: vault.pending_withdrawal,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Authorized program records a shortfall the vault's collateral could not cover.
+    pub fn record_bad_debt(ctx: Context<RecordBadDebt>, amount: u64) -> Result<()> {
+        require!(amount > 0, VaultError::InvalidAmount);
+
+        let now = Clock::get()?.unix_timestamp;
+        ctx.accounts.global_config.record_authorized_activity(now);
+
+        let vault = &mut ctx.accounts.vault;
+        vault.bad_debt = vault
+            .bad_debt
+            .checked_add(amount)
+            .ok_or(VaultError::MathOverflow)?;
+
+        emit!(BadDebtRecordedEvent {
+            vault: vault.key(),
+            caller: ctx.accounts.caller_program.key(),
+            amount,
+            new_bad_debt: vault.bad_debt,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Authorized program pays down a vault's bad debt from the insurance
+    /// fund, crediting the vault that absorbed the loss.
+    pub fn cover_bad_debt(ctx: Context<CoverBadDebt>, amount: u64) -> Result<()> {
+        require!(amount > 0, VaultError::InvalidAmount);
+        require!(amount <= ctx.accounts.vault.bad_debt, VaultError::BadDebtExceeded);
+        require!(
+            ctx.accounts.insurance_fund.balance >= amount,
+            VaultError::InsufficientInsuranceFund
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        ctx.accounts.global_config.record_authorized_activity(now);
+
+        let fund_ai = ctx.accounts.insurance_fund.to_account_info();
+        let fund = &mut ctx.accounts.insurance_fund;
+
+        let seeds = &[
+            b"insurance_fund",
+            fund.mint.as_ref(),
+            &[fund.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let recipient_balance_before = ctx.accounts.recipient_token_account.amount;
+
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.insurance_fund_token_account.to_account_info(),
+                    to: ctx.accounts.recipient_token_account.to_account_info(),
+                    authority: fund_ai,
+                    mint: ctx.accounts.mint.to_account_info(),
+                },
+                signer,
+            ),
+            amount,
+            ctx.accounts.mint.decimals,
+        )?;
+
+        ctx.accounts.recipient_token_account.reload()?;
+        let received = ctx
+            .accounts
+            .recipient_token_account
+            .amount
+            .checked_sub(recipient_balance_before)
+            .ok_or(VaultError::MathOverflow)?;
+
+        fund.balance = fund
+            .balance
+            .checked_sub(amount)
+            .ok_or(VaultError::MathOverflow)?;
+        fund.total_drawn = fund
+            .total_drawn
+            .checked_add(amount)
+            .ok_or(VaultError::MathOverflow)?;
+
+        let recipient = &mut ctx.accounts.recipient_vault;
+        recipient.total_balance = recipient
+            .total_balance
+            .checked_add(received)
+            .ok_or(VaultError::MathOverflow)?;
+        recipient.available_balance = recipient
+            .available_balance
+            .checked_add(received)
+            .ok_or(VaultError::MathOverflow)?;
+
+        ctx.accounts.tvl_counter.add(received)?;
+
+        // Only what reached the recipient counts as covered
+        let vault = &mut ctx.accounts.vault;
+        vault.bad_debt = vault
+            .bad_debt
+            .checked_sub(received)
+            .ok_or(VaultError::MathOverflow)?;
+
+        emit!(BadDebtCoveredEvent {
+            vault: vault.key(),
+            recipient_vault: recipient.key(),
+            caller: ctx.accounts.caller_program.key(),
+            amount: received,
+            new_bad_debt: vault.bad_debt,
+            new_fund_balance: fund.balance,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Authorized program spreads a vault's bad debt across the vaults in
+    /// `remaining_accounts`, pro-rata to their available balance. Locked
+    /// collateral is never touched.
+    pub fn socialize_bad_debt<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SocializeBadDebt<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        require!(amount > 0, VaultError::InvalidAmount);
+        require!(amount <= ctx.accounts.vault.bad_debt, VaultError::BadDebtExceeded);
+
+        let remaining = ctx.remaining_accounts;
+        require!(
+            !remaining.is_empty() && remaining.len().is_multiple_of(2),
+            VaultError::InvalidVaultAccount
+        );
+
+        let debtor_key = ctx.accounts.vault.key();
+        let recipient_key = ctx.accounts.recipient_vault.key();
+        let mint_key = ctx.accounts.mint.key();
+        let now = Clock::get()?.unix_timestamp;
+
+        ctx.accounts.global_config.record_authorized_activity(now);
+
+        let mut contributors: Vec<(Account<'info, CollateralVault>, &'info AccountInfo<'info>)> =
+            Vec::with_capacity(remaining.len() / 2);
+
+        for pair in remaining.chunks(2) {
+            let vault: Account<'info, CollateralVault> = Account::try_from(&pair[0])?;
+            let token_account = &pair[1];
+
+            let expected = Pubkey::create_program_address(
+                &[b"vault", vault.owner.as_ref(), vault.mint.as_ref(), &[vault.bump]],
+                &crate::ID,
+            )
+            .map_err(|_| VaultError::InvalidVaultAccount)?;
+
+            require!(
+                vault.key() == expected
+                    && vault.mint == mint_key
+                    && token_account.key() == vault.token_account
+                    && pair[0].is_writable
+                    && token_account.is_writable,
+                VaultError::InvalidVaultAccount
+            );
+            require!(
+                vault.key() != debtor_key && vault.key() != recipient_key,
+                VaultError::SameVault
+            );
+            require!(
+                !contributors.iter().any(|(v, _)| v.key() == vault.key()),
+                VaultError::InvalidVaultAccount
+            );
+
+            contributors.push((vault, token_account));
+        }
+
+        let weights: Vec<u64> = contributors
+            .iter()
+            .map(|(v, _)| v.available_balance)
+            .collect();
+        let shares = pro_rata_shares(amount, &weights)?;
+
+        let recipient_balance_before = ctx.accounts.recipient_token_account.amount;
+
+        for ((vault, token_account), share) in contributors.iter_mut().zip(shares) {
+            if share == 0 {
+                continue;
+            }
+
+            let seeds = &[
+                b"vault",
+                vault.owner.as_ref(),
+                vault.mint.as_ref(),
+                &[vault.bump],
+            ];
+            let signer = &[&seeds[..]];
+
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: token_account.to_account_info(),
+                        to: ctx.accounts.recipient_token_account.to_account_info(),
+                        authority: vault.to_account_info(),
+                        mint: ctx.accounts.mint.to_account_info(),
+                    },
+                    signer,
+                ),
+                share,
+                ctx.accounts.mint.decimals,
+            )?;
+
+            vault.available_balance = vault
+                .available_balance
+                .checked_sub(share)
+                .ok_or(VaultError::MathOverflow)?;
+            vault.total_balance = vault
+                .total_balance
+                .checked_sub(share)
+                .ok_or(VaultError::MathOverflow)?;
+            vault.exit(&crate::ID)?;
+
+            emit!(LossSocializedEvent {
+                vault: vault.key(),
+                debtor_vault: debtor_key,
+                recipient_vault: recipient_key,
+                amount: share,
+                new_total_balance: vault.total_balance,
+                timestamp: now,
+            });
+        }
+
+        ctx.accounts.recipient_token_account.reload()?;
+        let received = ctx
+            .accounts
+            .recipient_token_account
+            .amount
+            .checked_sub(recipient_balance_before)
+            .ok_or(VaultError::MathOverflow)?;
+
+        let recipient = &mut ctx.accounts.recipient_vault;
+        recipient.total_balance = recipient
+            .total_balance
+            .checked_add(received)
+            .ok_or(VaultError::MathOverflow)?;
+        recipient.available_balance = recipient
+            .available_balance
+            .checked_add(received)
+            .ok_or(VaultError::MathOverflow)?;
+
+        ctx.accounts
+            .tvl_counter
+            .sub(amount.checked_sub(received).ok_or(VaultError::MathOverflow)?)?;
+
+        let vault = &mut ctx.accounts.vault;
+        vault.bad_debt = vault
+            .bad_debt
+            .checked_sub(received)
+            .ok_or(VaultError::MathOverflow)?;
+
+        emit!(BadDebtSocializedEvent {
+            vault: debtor_key,
+            recipient_vault: recipient_key,
+            caller: ctx.accounts.caller_program.key(),
+            amount: received,
+            vault_count: contributors.len() as u32,
+            new_bad_debt: vault.bad_debt,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Admin sets how a mint is priced for `value_vault`.
+    pub fn set_collateral_config(
+        ctx: Context<SetCollateralConfig>,
+        oracle: Pubkey,
+        haircut_bps: u16,
+        max_staleness: i64,
+    ) -> Result<()> {
+        require!(
+            (haircut_bps as u64) <= oracle::BPS_DENOMINATOR && max_staleness > 0,
+            VaultError::InvalidCollateralConfig
+        );
+
+        let config = &mut ctx.accounts.collateral_config;
+        config.mint = ctx.accounts.mint.key();
+        config.oracle = oracle;
+        config.haircut_bps = haircut_bps;
+        config.max_staleness = max_staleness;
+        config.bump = ctx.bumps.collateral_config;
+
+        emit!(CollateralConfigUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            mint: config.mint,
+            oracle,
+            haircut_bps,
+            max_staleness,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Read-only USD valuation of a vault, returned to the caller (CPI return
+    /// data). Sub-account allocations count towards `total_value` only.
+    pub fn value_vault(ctx: Context<ValueVault>) -> Result<VaultValuation> {
+        let vault = &ctx.accounts.vault;
+        let config = &ctx.accounts.collateral_config;
+        let decimals = ctx.accounts.mint.decimals;
+
+        let price = oracle::load_oracle_price(&ctx.accounts.oracle.to_account_info())?;
+        config.check_fresh(price.publish_time, Clock::get()?.unix_timestamp)?;
+
+        Ok(VaultValuation {
+            vault: vault.key(),
+            mint: vault.mint,
+            total_value: oracle::value_collateral(
+                vault.total_balance,
+                decimals,
+                &price,
+                config.haircut_bps,
+            )?,
+            available_value: oracle::value_collateral(
+                vault.available_balance,
+                decimals,
+                &price,
+                config.haircut_bps,
+            )?,
+            locked_value: oracle::value_collateral(
+                vault.locked_balance,
+                decimals,
+                &price,
+                config.haircut_bps,
+            )?,
+            price: price.price,
+            expo: price.expo,
+            publish_time: price.publish_time,
+            haircut_bps: config.haircut_bps,
+        })
+    }
+
+    /// Read-only. Sets return data to a `VaultStateSnapshot` so CPI callers
+    /// don't depend on the `CollateralVault` layout.
+    pub fn get_vault_state(ctx: Context<GetVaultState>) -> Result<VaultStateSnapshot> {
+        let vault = &ctx.accounts.vault;
+        Ok(vault.snapshot(vault.key()))
+    }
+
+    pub fn demo_lock(
+        ctx: Context<LockCollateral>,
+        position_id: u64,
+        amount: u64,
+        expires_at: Option<i64>,
+    ) -> Result<()> {
+        lock_collateral(ctx, position_id, amount, expires_at)
+    }
+
+    pub fn demo_unlock(
+        ctx: Context<UnlockCollateral>,
+        position_id: u64,
+        amount: u64,
+    ) -> Result<()> {
+        unlock_collateral(ctx, position_id, amount)
+    }
+
+    pub fn demo_transfer_collateral(
+        ctx: Context<TransferCollateral>,
+        amount: u64,
+        mode: TransferMode,
+    ) -> Result<()> {
+        transfer_collateral(ctx, amount, mode)
+    }
+}
+
+/// Token-2022 mint extensions the vault can custody safely. Anything else
+/// (permanent delegate, transfer hooks, confidential transfers, default-frozen
+/// accounts, ...) could move or freeze collateral behind the ledger's back.
+const SUPPORTED_MINT_EXTENSIONS: &[ExtensionType] = &[
+    ExtensionType::TransferFeeConfig,
+    ExtensionType::InterestBearingConfig,
+    ExtensionType::MetadataPointer,
+    ExtensionType::TokenMetadata,
+    ExtensionType::GroupPointer,
+    ExtensionType::TokenGroup,
+    ExtensionType::GroupMemberPointer,
+    ExtensionType::TokenGroupMember,
+];
+
+fn ensure_supported_mint(mint: &AccountInfo) -> Result<()> {
+    if *mint.owner != token_2022::ID {
+        return Ok(());
+    }
+
+    let data = mint.try_borrow_data()?;
+    let state = StateWithExtensions::<MintState>::unpack(&data)?;
+
+    for extension in state.get_extension_types()? {
+        require!(
+            SUPPORTED_MINT_EXTENSIONS.contains(&extension),
+            VaultError::UnsupportedMintExtension
+        );
+    }
+
+    Ok(())
+}
+
+/// Transfer fees withheld in a Token-2022 token account (0 for SPL Token
+/// accounts and mints without a transfer fee).
+fn withheld_transfer_fees(token_account: &AccountInfo) -> Result<u64> {
+    if *token_account.owner != token_2022::ID {
+        return Ok(0);
+    }
+
+    let data = token_account.try_borrow_data()?;
+    let state = StateWithExtensions::<TokenAccountState>::unpack(&data)?;
+
+    Ok(state
+        .get_extension::<TransferFeeAmount>()
+        .map(|fee| u64::from(fee.withheld_amount))
+        .unwrap_or(0))
+}
+
+/// Pulls `amount` from `from` into the vault's token account and returns
+/// what arrived (less on Token-2022 mints with a transfer fee).
+fn pull_into_vault<'info>(
+    from: &InterfaceAccount<'info, TokenAccount>,
+    authority: &Signer<'info>,
+    vault_token_account: &mut InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    token_program: &Interface<'info, TokenInterface>,
+    amount: u64,
+) -> Result<u64> {
+    let balance_before = vault_token_account.amount;
+
+    token_interface::transfer_checked(
+        CpiContext::new(
+            token_program.to_account_info(),
+            TransferChecked {
+                from: from.to_account_info(),
+                to: vault_token_account.to_account_info(),
+                authority: authority.to_account_info(),
+                mint: mint.to_account_info(),
+            },
+        ),
+        amount,
+        mint.decimals,
+    )?;
+
+    vault_token_account.reload()?;
+    vault_token_account
+        .amount
+        .checked_sub(balance_before)
+        .ok_or(VaultError::MathOverflow.into())
+}
+
+/// Pays `amount` out of the vault's token account under the vault PDA's
+/// signature. Returns `(debited, received)`: what left the vault and what
+/// reached `to`, which differ on fee mints.
+fn pay_out_of_vault<'info>(
+    vault: &Account<'info, CollateralVault>,
+    vault_token_account: &mut InterfaceAccount<'info, TokenAccount>,
+    to: &mut InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    token_program: &Interface<'info, TokenInterface>,
+    amount: u64,
+) -> Result<(u64, u64)> {
+    let seeds = &[
+        b"vault",
+        vault.owner.as_ref(),
+        vault.mint.as_ref(),
+        &[vault.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    let vault_balance_before = vault_token_account.amount;
+    let to_balance_before = to.amount;
+
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            TransferChecked {
+                from: vault_token_account.to_account_info(),
+                to: to.to_account_info(),
+                authority: vault.to_account_info(),
+                mint: mint.to_account_info(),
+            },
+            signer,
+        ),
+        amount,
+        mint.decimals,
+    )?;
+
+    vault_token_account.reload()?;
+    to.reload()?;
+    let debited = vault_balance_before
+        .checked_sub(vault_token_account.amount)
+        .ok_or(VaultError::MathOverflow)?;
+    let received = to
+        .amount
+        .checked_sub(to_balance_before)
+        .ok_or(VaultError::MathOverflow)?;
+
+    Ok((debited, received))
+}
+
+/// Reads the snapshot left in return data by a `get_vault_state` CPI.
+#[cfg(feature = "cpi")]
+pub fn read_vault_state_return_data() -> Result<VaultStateSnapshot> {
+    let (program_id, data) = anchor_lang::solana_program::program::get_return_data()
+        .ok_or(VaultError::InvalidVaultStateData)?;
+    require_keys_eq!(program_id, crate::ID, VaultError::InvalidVaultStateData);
+    VaultStateSnapshot::decode(&data)
+}
+
+/// Invokes `get_vault_state` and decodes its return data.
+#[cfg(feature = "cpi")]
+pub fn fetch_vault_state<'info>(
+    ctx: CpiContext<'_, '_, '_, 'info, cpi::accounts::GetVaultState<'info>>,
+) -> Result<VaultStateSnapshot> {
+    cpi::get_vault_state(ctx)?;
+    read_vault_state_return_data()
+}
+
+/// Splits `total` across `weights` proportionally, rounding down and handing
+/// the remainder out one unit at a time in order. No share exceeds its weight.
+pub fn pro_rata_shares(total: u64, weights: &[u64]) -> Result<Vec<u64>> {
+    let weight_sum = weights
+        .iter()
+        .try_fold(0u128, |acc, w| acc.checked_add(*w as u128))
+        .ok_or(VaultError::MathOverflow)?;
+    require!(
+        weight_sum >= total as u128,
+        VaultError::InsufficientAvailableBalance
+    );
+
+    if total == 0 {
+        return Ok(vec![0; weights.len()]);
+    }
+
+    let mut shares: Vec<u64> = weights
+        .iter()
+        .map(|w| (total as u128 * *w as u128 / weight_sum) as u64)
+        .collect();
+
+    let mut remainder = total - shares.iter().sum::<u64>();
+    for (share, weight) in shares.iter_mut().zip(weights) {
+        if remainder == 0 {
+            break;
+        }
+        if *share < *weight {
+            *share += 1;
+            remainder -= 1;
+        }
+    }
+
+    Ok(shares)
+}
+
+#[derive(Accounts)]
+pub struct InitializeVault<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_INITIALIZE) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        init,
+        payer = user,
+        space = CollateralVault::LEN,
+        seeds = [b"vault", user.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub vault: Account<'info, CollateralVault>,
+
+    /// CHECK: SPL token account that will hold the collateral. Created via CPI.
+    #[account(mut)]
+    pub vault_token_account: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub token_mint: InterfaceAccount<'info, Mint>,  // collateral mint (USDC, USDT, ...)
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Deposit<'info> {
+    /// Vault owner or one of its delegates
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_DEPOSIT) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.owner.as_ref(), mint.key().as_ref()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, CollateralVault>,
+
+    /// Required when `user` is not the vault owner
+    #[account(
+        seeds = [b"delegate", vault.key().as_ref(), user.key().as_ref()],
+        bump = delegate.bump,
+    )]
+    pub delegate: Option<Account<'info, Delegate>>,
+
+    #[account(mut)]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = vault_token_account.key() == vault.token_account,
+        constraint = vault_token_account.mint == mint.key(),
+    )]
+    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        constraint = user_token_account.mint == mint.key(),
+    )]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = TvlCounter::LEN,
+        seeds = [b"tvl", mint.key().as_ref()],
+        bump
+    )]
+    pub tvl_counter: Account<'info, TvlCounter>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DepositFor<'info> {
+    #[account(mut)]
+    pub funder: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_DEPOSIT) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.owner.as_ref(), mint.key().as_ref()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, CollateralVault>,
+
+    #[account(
+        mut,
+        constraint = funder_token_account.mint == mint.key(),
+    )]
+    pub funder_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = vault_token_account.key() == vault.token_account,
+        constraint = vault_token_account.mint == mint.key(),
+    )]
+    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = funder,
+        space = TvlCounter::LEN,
+        seeds = [b"tvl", mint.key().as_ref()],
+        bump
+    )]
+    pub tvl_counter: Account<'info, TvlCounter>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Withdraw<'info> {
+    /// Vault owner or one of its delegates
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_WITHDRAW) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.owner.as_ref(), mint.key().as_ref()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, CollateralVault>,
+
+    /// Vault guardian; required above `vault.cosign_threshold`
+    pub guardian: Option<Signer<'info>>,
+
+    /// Required when `user` is not the vault owner
+    #[account(
+        mut,
+        seeds = [b"delegate", vault.key().as_ref(), user.key().as_ref()],
+        bump = delegate.bump,
+    )]
+    pub delegate: Option<Account<'info, Delegate>>,
+
+    #[account(
+        mut,
+        constraint = vault_token_account.key() == vault.token_account,
+        constraint = vault_token_account.mint == mint.key(),
+    )]
+    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = user_token_account.mint == mint.key(),
+        constraint = user_token_account.owner == vault.owner @ VaultError::Unauthorized,
+    )]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        constraint = user_token_account.mint == mint.key(),
+    )]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = TvlCounter::LEN,
+        seeds = [b"tvl", mint.key().as_ref()],
+        bump
+    )]
+    pub tvl_counter: Account<'info, TvlCounter>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawTo<'info> {
+    /// Vault owner or one of its delegates
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_WITHDRAW) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.owner.as_ref(), mint.key().as_ref()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, CollateralVault>,
+
+    /// Vault guardian; required above `vault.cosign_threshold`
+    pub guardian: Option<Signer<'info>>,
+
+    /// Required when `user` is not the vault owner
+    #[account(
+        mut,
+        seeds = [b"delegate", vault.key().as_ref(), user.key().as_ref()],
+        bump = delegate.bump,
+    )]
+    pub delegate: Option<Account<'info, Delegate>>,
+
+    /// Required once `vault.allowlist_enabled` is set, and for delegates
+    #[account(
+        seeds = [b"withdraw_allowlist", vault.key().as_ref()],
+        bump = withdraw_allowlist.bump,
+    )]
+    pub withdraw_allowlist: Option<Account<'info, WithdrawAllowlist>>,
+
+    #[account(
+        mut,
+        constraint = vault_token_account.key() == vault.token_account,
+        constraint = vault_token_account.mint == mint.key(),
+    )]
+    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = destination_token_account.mint == mint.key(),
+        constraint = destination_token_account.key() != vault.token_account @ VaultError::SameVault,
+    )]
+    pub destination_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = TvlCounter::LEN,
+        seeds = [b"tvl", mint.key().as_ref()],
+        bump
+    )]
+    pub tvl_counter: Account<'info, TvlCounter>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AddWithdrawDestination<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_SETTINGS) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.mint.as_ref()],
+        bump = vault.bump,
+        constraint = vault.owner == user.key(),
+    )]
+    pub vault: Account<'info, CollateralVault>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = WithdrawAllowlist::LEN,
+        seeds = [b"withdraw_allowlist", vault.key().as_ref()],
+        bump
+    )]
+    pub withdraw_allowlist: Account<'info, WithdrawAllowlist>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveWithdrawDestination<'info> {
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_SETTINGS) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        seeds = [b"vault", user.key().as_ref(), vault.mint.as_ref()],
+        bump = vault.bump,
+        constraint = vault.owner == user.key(),
+    )]
+    pub vault: Account<'info, CollateralVault>,
+
+    #[account(
+        mut,
+        seeds = [b"withdraw_allowlist", vault.key().as_ref()],
+        bump = withdraw_allowlist.bump,
+    )]
+    pub withdraw_allowlist: Account<'info, WithdrawAllowlist>,
+}
+
+#[derive(Accounts)]
+pub struct EmergencyWithdraw<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), mint.key().as_ref()],
+        bump = vault.bump,
+        constraint = vault.owner == user.key(),
+    )]
+    pub vault: Account<'info, CollateralVault>,
+
+    #[account(
+        mut,
+        constraint = vault_token_account.key() == vault.token_account,
+        constraint = vault_token_account.mint == mint.key(),
+    )]
+    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = user_token_account.mint == mint.key(),
+    )]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = TvlCounter::LEN,
+        seeds = [b"tvl", mint.key().as_ref()],
+        bump
+    )]
+    pub tvl_counter: Account<'info, TvlCounter>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RequestWithdrawal<'info> {
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_WITHDRAW) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.mint.as_ref()],
+        bump = vault.bump,
+        constraint = vault.owner == user.key(),
+    )]
+    pub vault: Account<'info, CollateralVault>,
+
+    /// Vault guardian; required above `vault.cosign_threshold`
+    pub guardian: Option<Signer<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct SetDelegate<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_SETTINGS) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.mint.as_ref()],
+        bump = vault.bump,
+        constraint = vault.owner == user.key(),
+    )]
+    pub vault: Account<'info, CollateralVault>,
+
+    /// CHECK: the session key being granted; only its address is stored
+    pub delegate_key: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = Delegate::LEN,
+        seeds = [b"delegate", vault.key().as_ref(), delegate_key.key().as_ref()],
+        bump
+    )]
+    pub delegate: Account<'info, Delegate>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeDelegate<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_SETTINGS) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.mint.as_ref()],
+        bump = vault.bump,
+        constraint = vault.owner == user.key(),
+    )]
+    pub vault: Account<'info, CollateralVault>,
+
+    #[account(
+        mut,
+        seeds = [b"delegate", vault.key().as_ref(), delegate.delegate.as_ref()],
+        bump = delegate.bump,
+        close = user,
+    )]
+    pub delegate: Account<'info, Delegate>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateVaultSettings<'info> {
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_SETTINGS) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.mint.as_ref()],
+        bump = vault.bump,
+        constraint = vault.owner == user.key(),
+    )]
+    pub vault: Account<'info, CollateralVault>,
+}
+
+#[derive(Accounts)]
+pub struct SetWithdrawalBlock<'info> {
+    /// CHECK: caller program (CPI), proven by `caller_authority` below
+    #[account(executable)]
+    pub caller_program: UncheckedAccount<'info>,
+
+    /// `caller_program`'s signing PDA, see `CALLER_AUTHORITY_SEED`
+    #[account(
+        seeds = [CALLER_AUTHORITY_SEED],
+        bump,
+        seeds::program = caller_program.key(),
+    )]
+    pub caller_authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_SETTINGS) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        seeds = [b"vault_authority"],
+        bump = vault_authority.bump,
+        constraint = vault_authority.is_authorized(&caller_program.key())
+            @ VaultError::Unauthorized,
+    )]
+    pub vault_authority: Account<'info, VaultAuthority>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.owner.as_ref(), vault.mint.as_ref()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, CollateralVault>,
+}
+
+#[derive(Accounts)]
+pub struct CloseVault<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_CLOSE) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), mint.key().as_ref()],
+        bump = vault.bump,
+        constraint = vault.owner == user.key(),
+        close = user,
+    )]
+    pub vault: Account<'info, CollateralVault>,
+
+    #[account(
+        mut,
+        constraint = vault_token_account.key() == vault.token_account,
+        constraint = vault_token_account.mint == mint.key(),
+    )]
+    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Receives any untracked tokens left in the vault token account
+    #[account(
+        mut,
+        constraint = user_token_account.mint == mint.key(),
+        constraint = user_token_account.owner == user.key() @ VaultError::Unauthorized,
+    )]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Required when `vault.allowlist_enabled`
+    #[account(
+        mut,
+        seeds = [b"withdraw_allowlist", vault.key().as_ref()],
+        bump = withdraw_allowlist.bump,
+        close = user,
+    )]
+    pub withdraw_allowlist: Option<Account<'info, WithdrawAllowlist>>,
+
+    /// Writable so Token-2022 withheld fees can be harvested into it
+    #[account(mut)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateVault<'info> {
+    /// Owner of the legacy vault; pays rent for the new accounts
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_INITIALIZE) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// CHECK: legacy vaults can't be deserialized as `CollateralVault`;
+    /// discriminator and size are checked in the handler
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref()],
+        bump,
+        owner = crate::ID @ VaultError::InvalidVaultAccount,
+    )]
+    pub legacy_vault: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = legacy_token_account.owner == legacy_vault.key(),
+        constraint = legacy_token_account.mint == mint.key(),
+    )]
+    pub legacy_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = user,
+        space = CollateralVault::LEN,
+        seeds = [b"vault", user.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub vault: Account<'info, CollateralVault>,
+
+    /// CHECK: SPL token account that will hold the collateral. Created via CPI.
+    #[account(mut)]
+    pub vault_token_account: UncheckedAccount<'info>,
+
+    /// Holds the legacy `locked_balance`; pass it only when that is non-zero
+    #[account(
+        init,
+        payer = user,
+        space = LockRecord::LEN,
+        seeds = [
+            b"lock",
+            vault.key().as_ref(),
+            LockRecord::LEGACY_CALLER.as_ref(),
+            &LockRecord::LEGACY_POSITION_ID.to_le_bytes(),
+        ],
+        bump
+    )]
+    pub lock_record: Option<Account<'info, LockRecord>>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = TvlCounter::LEN,
+        seeds = [b"tvl", mint.key().as_ref()],
+        bump
+    )]
+    pub tvl_counter: Account<'info, TvlCounter>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeVaultAuthority<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = VaultAuthority::LEN,
+        seeds = [b"vault_authority"],
+        bump
+    )]
+    pub vault_authority: Account<'info, VaultAuthority>,
+
+    /// Only the upgrade authority of this program may create the whitelist.
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::CollateralVault>,
+
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(admin.key())
+            @ VaultError::Unauthorized,
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateVaultAuthority<'info> {
+    /// Upgrade authority of this program; pays the added rent
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    /// CHECK: a legacy whitelist can't be deserialized as `VaultAuthority`;
+    /// discriminator and size are checked in the handler
+    #[account(
+        mut,
+        seeds = [b"vault_authority"],
+        bump,
+        owner = crate::ID @ VaultError::InvalidVaultAuthorityAccount,
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::CollateralVault>,
+
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(admin.key())
+            @ VaultError::Unauthorized,
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateVaultAuthority<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_authority"],
+        bump = vault_authority.bump,
+        has_one = admin @ VaultError::Unauthorized,
+    )]
+    pub vault_authority: Account<'info, VaultAuthority>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAdmin<'info> {
+    pub new_admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_authority"],
+        bump = vault_authority.bump,
+        constraint = vault_authority.pending_admin == Some(new_admin.key())
+            @ VaultError::NoPendingAdmin,
+    )]
+    pub vault_authority: Account<'info, VaultAuthority>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeGlobalConfig<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [b"vault_authority"],
+        bump = vault_authority.bump,
+        has_one = admin @ VaultError::Unauthorized,
+    )]
+    pub vault_authority: Account<'info, VaultAuthority>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = GlobalConfig::LEN,
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateGlobalConfig<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [b"vault_authority"],
+        bump = vault_authority.bump,
+        has_one = admin @ VaultError::Unauthorized,
+    )]
+    pub vault_authority: Account<'info, VaultAuthority>,
+
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
+
+#[derive(Accounts)]
+pub struct ReconcileTvl<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [b"vault_authority"],
+        bump = vault_authority.bump,
+        has_one = admin @ VaultError::Unauthorized,
+    )]
+    pub vault_authority: Account<'info, VaultAuthority>,
+
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = TvlCounter::LEN,
+        seeds = [b"tvl", mint.key().as_ref()],
+        bump
+    )]
+    pub tvl_counter: Account<'info, TvlCounter>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetPaused<'info> {
+    /// Admin or guardian (pausing); admin only (resuming)
+    #[account(
+        constraint = authority.key() == vault_authority.admin
+            || authority.key() == global_config.guardian @ VaultError::Unauthorized,
+    )]
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"vault_authority"],
+        bump = vault_authority.bump,
+    )]
+    pub vault_authority: Account<'info, VaultAuthority>,
+
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
+
+#[derive(Accounts)]
+#[instruction(position_id: u64)]
+pub struct LockCollateral<'info> {
+    /// CHECK: caller program (CPI), proven by `caller_authority` below
+    #[account(executable)]
+    pub caller_program: UncheckedAccount<'info>,
+
+    /// `caller_program`'s signing PDA, see `CALLER_AUTHORITY_SEED`
+    #[account(
+        seeds = [CALLER_AUTHORITY_SEED],
+        bump,
+        seeds::program = caller_program.key(),
+    )]
+    pub caller_authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"vault_authority"],
+        bump = vault_authority.bump,
+        constraint = vault_authority.is_authorized(&caller_program.key())
+            @ VaultError::Unauthorized,
+    )]
+    pub vault_authority: Account<'info, VaultAuthority>,
+
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_LOCK) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.owner.as_ref(), vault.mint.as_ref()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, CollateralVault>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = LockRecord::LEN,
+        seeds = [
+            b"lock",
+            vault.key().as_ref(),
+            caller_program.key().as_ref(),
+            &position_id.to_le_bytes(),
+        ],
+        bump
+    )]
+    pub lock_record: Account<'info, LockRecord>,
+
+    /// Pays rent for a new lock record
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(position_id: u64)]
+pub struct UnlockCollateral<'info> {
+    /// CHECK: caller program (CPI), proven by `caller_authority` below
+    #[account(executable)]
+    pub caller_program: UncheckedAccount<'info>,
+
+    /// `caller_program`'s signing PDA, see `CALLER_AUTHORITY_SEED`
+    #[account(
+        seeds = [CALLER_AUTHORITY_SEED],
+        bump,
+        seeds::program = caller_program.key(),
+    )]
+    pub caller_authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"vault_authority"],
+        bump = vault_authority.bump,
+        constraint = vault_authority.is_authorized(&caller_program.key())
+            @ VaultError::Unauthorized,
+    )]
+    pub vault_authority: Account<'info, VaultAuthority>,
+
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_UNLOCK) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.owner.as_ref(), vault.mint.as_ref()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, CollateralVault>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"lock",
+            vault.key().as_ref(),
+            caller_program.key().as_ref(),
+            &position_id.to_le_bytes(),
+        ],
+        bump = lock_record.bump,
+    )]
+    pub lock_record: Account<'info, LockRecord>,
+
+    /// CHECK: receives the record's rent when it closes
+    #[account(mut, address = lock_record.rent_payer)]
+    pub rent_receiver: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ReleaseExpiredLock<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.owner.as_ref(), vault.mint.as_ref()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, CollateralVault>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"lock",
+            vault.key().as_ref(),
+            lock_record.caller_program.as_ref(),
+            &lock_record.position_id.to_le_bytes(),
+        ],
+        bump = lock_record.bump,
+        has_one = vault,
+    )]
+    pub lock_record: Account<'info, LockRecord>,
+
+    /// CHECK: receives the record's rent
+    #[account(mut, address = lock_record.rent_payer)]
+    pub rent_receiver: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(index: u16)]
+pub struct CreateSubAccount<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_SUB_ACCOUNT) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.mint.as_ref()],
+        bump = vault.bump,
+        constraint = vault.owner == user.key(),
+    )]
+    pub vault: Account<'info, CollateralVault>,
+
+    #[account(
+        init,
+        payer = user,
+        space = SubAccount::LEN,
+        seeds = [b"sub_account", vault.key().as_ref(), &index.to_le_bytes()],
+        bump
+    )]
+    pub sub_account: Account<'info, SubAccount>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseSubAccount<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_SUB_ACCOUNT) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.mint.as_ref()],
+        bump = vault.bump,
+        constraint = vault.owner == user.key(),
+    )]
+    pub vault: Account<'info, CollateralVault>,
+
+    #[account(
+        mut,
+        seeds = [b"sub_account", vault.key().as_ref(), &sub_account.index.to_le_bytes()],
+        bump = sub_account.bump,
+        close = user,
+    )]
+    pub sub_account: Account<'info, SubAccount>,
+}
+
+#[derive(Accounts)]
+pub struct MoveSubAccountFunds<'info> {
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_SUB_ACCOUNT) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.mint.as_ref()],
+        bump = vault.bump,
+        constraint = vault.owner == user.key(),
+    )]
+    pub vault: Account<'info, CollateralVault>,
+
+    #[account(
+        mut,
+        seeds = [b"sub_account", vault.key().as_ref(), &from_sub_account.index.to_le_bytes()],
+        bump = from_sub_account.bump,
+    )]
+    pub from_sub_account: Option<Account<'info, SubAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"sub_account", vault.key().as_ref(), &to_sub_account.index.to_le_bytes()],
+        bump = to_sub_account.bump,
+    )]
+    pub to_sub_account: Option<Account<'info, SubAccount>>,
+}
+
+#[derive(Accounts)]
+#[instruction(position_id: u64)]
+pub struct LockSubAccountCollateral<'info> {
+    /// CHECK: caller program (CPI), proven by `caller_authority` below
+    #[account(executable)]
+    pub caller_program: UncheckedAccount<'info>,
+
+    /// `caller_program`'s signing PDA, see `CALLER_AUTHORITY_SEED`
+    #[account(
+        seeds = [CALLER_AUTHORITY_SEED],
+        bump,
+        seeds::program = caller_program.key(),
+    )]
+    pub caller_authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"vault_authority"],
+        bump = vault_authority.bump,
+        constraint = vault_authority.is_authorized(&caller_program.key())
+            @ VaultError::Unauthorized,
+    )]
+    pub vault_authority: Account<'info, VaultAuthority>,
+
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_LOCK) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// Parent vault: epoch and lock count
+    #[account(mut, address = sub_account.vault)]
+    pub vault: Account<'info, CollateralVault>,
+
+    #[account(
+        mut,
+        seeds = [b"sub_account", sub_account.vault.as_ref(), &sub_account.index.to_le_bytes()],
+        bump = sub_account.bump,
+    )]
+    pub sub_account: Account<'info, SubAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = LockRecord::LEN,
+        seeds = [
+            b"lock",
+            sub_account.key().as_ref(),
+            caller_program.key().as_ref(),
+            &position_id.to_le_bytes(),
+        ],
+        bump
+    )]
+    pub lock_record: Account<'info, LockRecord>,
+
+    /// Pays rent for a new lock record
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(position_id: u64)]
+pub struct UnlockSubAccountCollateral<'info> {
+    /// CHECK: caller program (CPI), proven by `caller_authority` below
+    #[account(executable)]
+    pub caller_program: UncheckedAccount<'info>,
+
+    /// `caller_program`'s signing PDA, see `CALLER_AUTHORITY_SEED`
+    #[account(
+        seeds = [CALLER_AUTHORITY_SEED],
+        bump,
+        seeds::program = caller_program.key(),
+    )]
+    pub caller_authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"vault_authority"],
+        bump = vault_authority.bump,
+        constraint = vault_authority.is_authorized(&caller_program.key())
+            @ VaultError::Unauthorized,
+    )]
+    pub vault_authority: Account<'info, VaultAuthority>,
+
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_UNLOCK) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// Parent vault: epoch and lock count
+    #[account(mut, address = sub_account.vault)]
+    pub vault: Account<'info, CollateralVault>,
+
+    #[account(
+        mut,
+        seeds = [b"sub_account", sub_account.vault.as_ref(), &sub_account.index.to_le_bytes()],
+        bump = sub_account.bump,
+    )]
+    pub sub_account: Account<'info, SubAccount>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"lock",
+            sub_account.key().as_ref(),
+            caller_program.key().as_ref(),
+            &position_id.to_le_bytes(),
+        ],
+        bump = lock_record.bump,
+    )]
+    pub lock_record: Account<'info, LockRecord>,
+
+    /// CHECK: receives the record's rent when it closes
+    #[account(mut, address = lock_record.rent_payer)]
+    pub rent_receiver: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ReleaseExpiredSubAccountLock<'info> {
+    /// Parent vault: epoch and lock count
+    #[account(mut, address = sub_account.vault)]
+    pub vault: Account<'info, CollateralVault>,
+
+    #[account(
+        mut,
+        seeds = [b"sub_account", sub_account.vault.as_ref(), &sub_account.index.to_le_bytes()],
+        bump = sub_account.bump,
+    )]
+    pub sub_account: Account<'info, SubAccount>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"lock",
+            sub_account.key().as_ref(),
+            lock_record.caller_program.as_ref(),
+            &lock_record.position_id.to_le_bytes(),
+        ],
+        bump = lock_record.bump,
+        constraint = lock_record.vault == sub_account.key(),
+    )]
+    pub lock_record: Account<'info, LockRecord>,
+
+    /// CHECK: receives the record's rent
+    #[account(mut, address = lock_record.rent_payer)]
+    pub rent_receiver: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(position_id: u64)]
+pub struct Liquidate<'info> {
+    /// CHECK: caller program (CPI), proven by `caller_authority` below
+    #[account(executable)]
+    pub caller_program: UncheckedAccount<'info>,
+
+    /// `caller_program`'s signing PDA, see `CALLER_AUTHORITY_SEED`
+    #[account(
+        seeds = [CALLER_AUTHORITY_SEED],
+        bump,
+        seeds::program = caller_program.key(),
+    )]
+    pub caller_authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"vault_authority"],
+        bump = vault_authority.bump,
+        constraint = vault_authority.is_authorized(&caller_program.key())
+            @ VaultError::Unauthorized,
+    )]
+    pub vault_authority: Account<'info, VaultAuthority>,
+
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_LIQUIDATE) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.owner.as_ref(), vault.mint.as_ref()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, CollateralVault>,
+
+    /// Lock of `position_id` by `caller_program`, on `sub_account` when given
+    #[account(
+        mut,
+        constraint = lock_record.vault == sub_account.as_ref().map_or(vault.key(), |s| s.key())
+            @ VaultError::LockRecordRequired,
+        constraint = lock_record.caller_program == caller_program.key() @ VaultError::Unauthorized,
+        constraint = lock_record.position_id == position_id @ VaultError::LockRecordRequired,
+    )]
+    pub lock_record: Account<'info, LockRecord>,
+
+    /// CHECK: receives the record's rent if the position is fully seized
+    #[account(mut, address = lock_record.rent_payer)]
+    pub rent_receiver: UncheckedAccount<'info>,
 
     #[account(
         mut,
@@ -387,135 +3967,527 @@ pub struct Deposit<'info> {
     pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
 
     #[account(
-        constraint = user_token_account.mint == mint.key(),
+        mut,
+        constraint = liquidator_token_account.mint == mint.key(),
+        constraint = liquidator_token_account.key() != vault.token_account @ VaultError::SameVault,
+    )]
+    pub liquidator_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"insurance_fund", mint.key().as_ref()],
+        bump = insurance_fund.bump,
+    )]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+
+    #[account(
+        mut,
+        constraint = insurance_fund_token_account.key() == insurance_fund.token_account,
+    )]
+    pub insurance_fund_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"tvl", mint.key().as_ref()],
+        bump = tvl_counter.bump,
     )]
+    pub tvl_counter: Account<'info, TvlCounter>,
+
+    #[account(constraint = vault.mint == mint.key())]
     pub mint: InterfaceAccount<'info, Mint>,
 
     pub token_program: Interface<'info, TokenInterface>,
+
+    /// Sub-account of `vault` the position was locked in
+    #[account(
+        mut,
+        seeds = [b"sub_account", vault.key().as_ref(), &sub_account.index.to_le_bytes()],
+        bump = sub_account.bump,
+    )]
+    pub sub_account: Option<Account<'info, SubAccount>>,
 }
 
 #[derive(Accounts)]
-pub struct Withdraw<'info> {
+pub struct InitializeInsuranceFund<'info> {
     #[account(mut)]
-    pub user: Signer<'info>,
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [b"vault_authority"],
+        bump = vault_authority.bump,
+        has_one = admin @ VaultError::Unauthorized,
+    )]
+    pub vault_authority: Account<'info, VaultAuthority>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = InsuranceFund::LEN,
+        seeds = [b"insurance_fund", mint.key().as_ref()],
+        bump
+    )]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+
+    /// CHECK: the fund's associated token account. Created via CPI.
+    #[account(mut)]
+    pub insurance_fund_token_account: UncheckedAccount<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FundInsurance<'info> {
+    pub funder: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_INSURANCE) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
 
     #[account(
         mut,
-        seeds = [b"vault", user.key().as_ref()],
+        seeds = [b"insurance_fund", mint.key().as_ref()],
+        bump = insurance_fund.bump,
+    )]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+
+    #[account(
+        mut,
+        constraint = insurance_fund_token_account.key() == insurance_fund.token_account,
+    )]
+    pub insurance_fund_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = funder_token_account.mint == mint.key(),
+    )]
+    pub funder_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct DrawInsuranceFund<'info> {
+    /// CHECK: caller program (CPI), proven by `caller_authority` below
+    #[account(executable)]
+    pub caller_program: UncheckedAccount<'info>,
+
+    /// `caller_program`'s signing PDA, see `CALLER_AUTHORITY_SEED`
+    #[account(
+        seeds = [CALLER_AUTHORITY_SEED],
+        bump,
+        seeds::program = caller_program.key(),
+    )]
+    pub caller_authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"vault_authority"],
+        bump = vault_authority.bump,
+        constraint = vault_authority.is_authorized(&caller_program.key())
+            @ VaultError::Unauthorized,
+    )]
+    pub vault_authority: Account<'info, VaultAuthority>,
+
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_INSURANCE) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"insurance_fund", mint.key().as_ref()],
+        bump = insurance_fund.bump,
+    )]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+
+    #[account(
+        mut,
+        constraint = insurance_fund_token_account.key() == insurance_fund.token_account,
+    )]
+    pub insurance_fund_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.owner.as_ref(), vault.mint.as_ref()],
         bump = vault.bump,
-        constraint = vault.owner == user.key(),
     )]
     pub vault: Account<'info, CollateralVault>,
 
     #[account(
         mut,
         constraint = vault_token_account.key() == vault.token_account,
-        constraint = vault_token_account.mint == mint.key(),
     )]
     pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
 
     #[account(
         mut,
-        constraint = user_token_account.mint == mint.key(),
+        seeds = [b"tvl", mint.key().as_ref()],
+        bump = tvl_counter.bump,
     )]
-    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub tvl_counter: Account<'info, TvlCounter>,
 
-    #[account(
-        constraint = user_token_account.mint == mint.key(),
-    )]
+    #[account(constraint = vault.mint == mint.key())]
     pub mint: InterfaceAccount<'info, Mint>,
 
     pub token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(Accounts)]
-pub struct InitializeVaultAuthority<'info> {
-    #[account(mut)]
-    pub admin: Signer<'info>,
+pub struct RecordBadDebt<'info> {
+    /// CHECK: caller program (CPI), proven by `caller_authority` below
+    #[account(executable)]
+    pub caller_program: UncheckedAccount<'info>,
+
+    /// `caller_program`'s signing PDA, see `CALLER_AUTHORITY_SEED`
+    #[account(
+        seeds = [CALLER_AUTHORITY_SEED],
+        bump,
+        seeds::program = caller_program.key(),
+    )]
+    pub caller_authority: Signer<'info>,
 
     #[account(
-        init,
-        payer = admin,
-        space = VaultAuthority::LEN,
         seeds = [b"vault_authority"],
-        bump
+        bump = vault_authority.bump,
+        constraint = vault_authority.is_authorized(&caller_program.key())
+            @ VaultError::Unauthorized,
     )]
     pub vault_authority: Account<'info, VaultAuthority>,
 
-    pub system_program: Program<'info, System>,
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_BAD_DEBT) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.owner.as_ref(), vault.mint.as_ref()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, CollateralVault>,
 }
 
 #[derive(Accounts)]
-pub struct LockCollateral<'info> {
-    /// CHECK: caller program (CPI)
+pub struct CoverBadDebt<'info> {
+    /// CHECK: caller program (CPI), proven by `caller_authority` below
+    #[account(executable)]
     pub caller_program: UncheckedAccount<'info>,
 
+    /// `caller_program`'s signing PDA, see `CALLER_AUTHORITY_SEED`
+    #[account(
+        seeds = [CALLER_AUTHORITY_SEED],
+        bump,
+        seeds::program = caller_program.key(),
+    )]
+    pub caller_authority: Signer<'info>,
+
     #[account(
         seeds = [b"vault_authority"],
         bump = vault_authority.bump,
-        constraint = vault_authority
-            .authorized_programs
-            .contains(&caller_program.key()),
+        constraint = vault_authority.is_authorized(&caller_program.key())
+            @ VaultError::Unauthorized,
     )]
     pub vault_authority: Account<'info, VaultAuthority>,
 
     #[account(
         mut,
-        seeds = [b"vault", vault.owner.as_ref()],
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_BAD_DEBT) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.owner.as_ref(), vault.mint.as_ref()],
         bump = vault.bump,
     )]
     pub vault: Account<'info, CollateralVault>,
+
+    #[account(
+        mut,
+        seeds = [b"insurance_fund", mint.key().as_ref()],
+        bump = insurance_fund.bump,
+    )]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+
+    #[account(
+        mut,
+        constraint = insurance_fund_token_account.key() == insurance_fund.token_account,
+    )]
+    pub insurance_fund_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", recipient_vault.owner.as_ref(), recipient_vault.mint.as_ref()],
+        bump = recipient_vault.bump,
+        constraint = recipient_vault.key() != vault.key() @ VaultError::SameVault,
+        constraint = recipient_vault.mint == vault.mint,
+    )]
+    pub recipient_vault: Account<'info, CollateralVault>,
+
+    #[account(
+        mut,
+        constraint = recipient_token_account.key() == recipient_vault.token_account,
+    )]
+    pub recipient_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"tvl", mint.key().as_ref()],
+        bump = tvl_counter.bump,
+    )]
+    pub tvl_counter: Account<'info, TvlCounter>,
+
+    #[account(constraint = vault.mint == mint.key())]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
+/// Contributing vaults are passed as `remaining_accounts` in writable
+/// `[vault, vault_token_account]` pairs.
 #[derive(Accounts)]
-pub struct UnlockCollateral<'info> {
-    /// CHECK: caller program (CPI)
+pub struct SocializeBadDebt<'info> {
+    /// CHECK: caller program (CPI), proven by `caller_authority` below
+    #[account(executable)]
     pub caller_program: UncheckedAccount<'info>,
 
+    /// `caller_program`'s signing PDA, see `CALLER_AUTHORITY_SEED`
+    #[account(
+        seeds = [CALLER_AUTHORITY_SEED],
+        bump,
+        seeds::program = caller_program.key(),
+    )]
+    pub caller_authority: Signer<'info>,
+
     #[account(
         seeds = [b"vault_authority"],
         bump = vault_authority.bump,
-        constraint = vault_authority
-            .authorized_programs
-            .contains(&caller_program.key()),
+        constraint = vault_authority.is_authorized(&caller_program.key())
+            @ VaultError::Unauthorized,
     )]
     pub vault_authority: Account<'info, VaultAuthority>,
 
     #[account(
         mut,
-        seeds = [b"vault", vault.owner.as_ref()],
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_BAD_DEBT) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.owner.as_ref(), vault.mint.as_ref()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, CollateralVault>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", recipient_vault.owner.as_ref(), recipient_vault.mint.as_ref()],
+        bump = recipient_vault.bump,
+        constraint = recipient_vault.key() != vault.key() @ VaultError::SameVault,
+        constraint = recipient_vault.mint == vault.mint,
+    )]
+    pub recipient_vault: Account<'info, CollateralVault>,
+
+    #[account(
+        mut,
+        constraint = recipient_token_account.key() == recipient_vault.token_account,
+    )]
+    pub recipient_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"tvl", mint.key().as_ref()],
+        bump = tvl_counter.bump,
+    )]
+    pub tvl_counter: Account<'info, TvlCounter>,
+
+    #[account(constraint = vault.mint == mint.key())]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct SetCollateralConfig<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_SETTINGS) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        seeds = [b"vault_authority"],
+        bump = vault_authority.bump,
+        has_one = admin @ VaultError::Unauthorized,
+    )]
+    pub vault_authority: Account<'info, VaultAuthority>,
+
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = CollateralConfig::LEN,
+        seeds = [b"collateral_config", mint.key().as_ref()],
+        bump
+    )]
+    pub collateral_config: Account<'info, CollateralConfig>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GetVaultState<'info> {
+    #[account(
+        seeds = [b"vault", vault.owner.as_ref(), vault.mint.as_ref()],
         bump = vault.bump,
     )]
     pub vault: Account<'info, CollateralVault>,
 }
 
+#[derive(Accounts)]
+pub struct ValueVault<'info> {
+    #[account(
+        seeds = [b"vault", vault.owner.as_ref(), vault.mint.as_ref()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, CollateralVault>,
+
+    #[account(
+        seeds = [b"collateral_config", vault.mint.as_ref()],
+        bump = collateral_config.bump,
+    )]
+    pub collateral_config: Account<'info, CollateralConfig>,
+
+    /// CHECK: price account pinned by `collateral_config`; parsed in `oracle`
+    #[account(address = collateral_config.oracle @ VaultError::InvalidOracle)]
+    pub oracle: UncheckedAccount<'info>,
+
+    #[account(constraint = vault.mint == mint.key())]
+    pub mint: InterfaceAccount<'info, Mint>,
+}
+
 #[derive(Accounts)]
 pub struct TransferCollateral<'info> {
-    /// CHECK: calling program
+    /// CHECK: calling program, proven by `caller_authority` below
+    #[account(executable)]
     pub caller_program: UncheckedAccount<'info>,
 
+    /// `caller_program`'s signing PDA, see `CALLER_AUTHORITY_SEED`
+    #[account(
+        seeds = [CALLER_AUTHORITY_SEED],
+        bump,
+        seeds::program = caller_program.key(),
+    )]
+    pub caller_authority: Signer<'info>,
+
     #[account(
         seeds = [b"vault_authority"],
         bump = vault_authority.bump,
-        constraint = vault_authority
-            .authorized_programs
-            .contains(&caller_program.key()),
+        constraint = vault_authority.is_authorized(&caller_program.key())
+            @ VaultError::Unauthorized,
     )]
     pub vault_authority: Account<'info, VaultAuthority>,
 
     #[account(
         mut,
-        seeds = [b"vault", from_vault.owner.as_ref()],
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_TRANSFER) @ VaultError::Paused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", from_vault.owner.as_ref(), from_vault.mint.as_ref()],
         bump = from_vault.bump,
     )]
     pub from_vault: Account<'info, CollateralVault>,
 
     #[account(
         mut,
-        seeds = [b"vault", to_vault.owner.as_ref()],
+        seeds = [b"vault", to_vault.owner.as_ref(), to_vault.mint.as_ref()],
         bump = to_vault.bump,
+        constraint = to_vault.key() != from_vault.key() @ VaultError::SameVault,
     )]
     pub to_vault: Account<'info, CollateralVault>,
+
+    #[account(
+        mut,
+        constraint = from_vault_token_account.key() == from_vault.token_account,
+        constraint = from_vault_token_account.mint == mint.key(),
+    )]
+    pub from_vault_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = to_vault_token_account.key() == to_vault.token_account,
+        constraint = to_vault_token_account.mint == mint.key(),
+    )]
+    pub to_vault_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"tvl", mint.key().as_ref()],
+        bump = tvl_counter.bump,
+    )]
+    pub tvl_counter: Account<'info, TvlCounter>,
+
+    #[account(
+        constraint = from_vault.mint == mint.key(),
+        constraint = to_vault.mint == mint.key(),
+    )]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// Required for `TransferMode::Locked`: the position being settled, on
+    /// `from_sub_account` when given
+    #[account(
+        mut,
+        constraint = lock_record.vault
+            == from_sub_account.as_ref().map_or(from_vault.key(), |s| s.key())
+            @ VaultError::LockRecordRequired,
+        constraint = lock_record.caller_program == caller_program.key() @ VaultError::Unauthorized,
+    )]
+    pub lock_record: Option<Account<'info, LockRecord>>,
+
+    /// CHECK: receives the lock record's rent once it is fully settled
+    #[account(mut)]
+    pub rent_receiver: Option<UncheckedAccount<'info>>,
+
+    /// Sub-account of `from_vault` to draw from (default: the vault itself)
+    #[account(
+        mut,
+        seeds = [b"sub_account", from_vault.key().as_ref(), &from_sub_account.index.to_le_bytes()],
+        bump = from_sub_account.bump,
+    )]
+    pub from_sub_account: Option<Account<'info, SubAccount>>,
 }
 
 #[cfg(test)]
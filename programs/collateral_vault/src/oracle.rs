@@ -0,0 +1,116 @@
+use anchor_lang::prelude::*;
+
+use crate::error::VaultError;
+
+/// USD values are reported with this many decimals
+pub const USD_DECIMALS: u32 = 6;
+
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+// Pyth (legacy push oracle) price account layout
+const PYTH_MAGIC: u32 = 0xa1b2_c3d4;
+const PYTH_ACCOUNT_TYPE_PRICE: u32 = 3;
+const PYTH_STATUS_TRADING: u32 = 1;
+
+const MAGIC_OFFSET: usize = 0;
+const ACCOUNT_TYPE_OFFSET: usize = 8;
+const EXPO_OFFSET: usize = 20;
+const TIMESTAMP_OFFSET: usize = 96;
+const AGG_PRICE_OFFSET: usize = 208;
+const AGG_CONF_OFFSET: usize = 216;
+const AGG_STATUS_OFFSET: usize = 224;
+
+/// Bytes needed to read every field above
+pub const PYTH_MIN_LEN: usize = AGG_STATUS_OFFSET + 4;
+
+/// Aggregate price read from an oracle account. The USD price of one whole
+/// token is `price * 10^expo`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct OraclePrice {
+    pub price: i64,
+    pub conf: u64,
+    pub expo: i32,
+    pub publish_time: i64,
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_i32(data: &[u8], offset: usize) -> i32 {
+    i32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+fn read_i64(data: &[u8], offset: usize) -> i64 {
+    i64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+/// Parses a Pyth-compatible price account. Only a positive price with
+/// `Trading` status is accepted.
+pub fn parse_pyth_price(data: &[u8]) -> Result<OraclePrice> {
+    require!(data.len() >= PYTH_MIN_LEN, VaultError::InvalidOracle);
+    require!(
+        read_u32(data, MAGIC_OFFSET) == PYTH_MAGIC
+            && read_u32(data, ACCOUNT_TYPE_OFFSET) == PYTH_ACCOUNT_TYPE_PRICE,
+        VaultError::InvalidOracle
+    );
+    require!(
+        read_u32(data, AGG_STATUS_OFFSET) == PYTH_STATUS_TRADING,
+        VaultError::InvalidOracle
+    );
+
+    let price = read_i64(data, AGG_PRICE_OFFSET);
+    require!(price > 0, VaultError::InvalidOracle);
+
+    Ok(OraclePrice {
+        price,
+        conf: read_u64(data, AGG_CONF_OFFSET),
+        expo: read_i32(data, EXPO_OFFSET),
+        publish_time: read_i64(data, TIMESTAMP_OFFSET),
+    })
+}
+
+pub fn load_oracle_price(oracle: &AccountInfo) -> Result<OraclePrice> {
+    let data = oracle.try_borrow_data()?;
+    parse_pyth_price(&data)
+}
+
+/// USD value (`USD_DECIMALS` decimals) of `amount` base units of a mint with
+/// `mint_decimals`, after taking `haircut_bps` off. Rounds down.
+pub fn value_collateral(
+    amount: u64,
+    mint_decimals: u8,
+    price: &OraclePrice,
+    haircut_bps: u16,
+) -> Result<u64> {
+    require!(
+        (haircut_bps as u64) <= BPS_DENOMINATOR,
+        VaultError::InvalidCollateralConfig
+    );
+
+    // `amount * price` is in units of 10^(expo - mint_decimals) USD
+    let raw = (amount as u128)
+        .checked_mul(price.price as u128)
+        .ok_or(VaultError::MathOverflow)?;
+
+    let scale = price.expo as i64 - mint_decimals as i64 + USD_DECIMALS as i64;
+    let factor = 10u128
+        .checked_pow(scale.unsigned_abs() as u32)
+        .ok_or(VaultError::MathOverflow)?;
+    let value = if scale >= 0 {
+        raw.checked_mul(factor).ok_or(VaultError::MathOverflow)?
+    } else {
+        raw / factor
+    };
+
+    let value = value
+        .checked_mul((BPS_DENOMINATOR - haircut_bps as u64) as u128)
+        .ok_or(VaultError::MathOverflow)?
+        / BPS_DENOMINATOR as u128;
+
+    u64::try_from(value).map_err(|_| VaultError::MathOverflow.into())
+}
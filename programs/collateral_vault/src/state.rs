@@ -1,11 +1,20 @@
 use anchor_lang::prelude::*;
 
+use crate::error::VaultError;
+
+/// Per-owner, per-mint collateral vault. PDA: `["vault", owner, mint]`.
+///
+/// New fields are carved out of `reserved` so `LEN` never changes; bump
+/// `CURRENT_VERSION` only when an existing field changes meaning.
 #[account]
 pub struct CollateralVault {
     /// Owner of the vault (user wallet)
     pub owner: Pubkey,
 
-    /// SPL token account holding USDT, owned by the vault PDA
+    /// Collateral mint held by this vault (USDC, USDT, ...)
+    pub mint: Pubkey,
+
+    /// SPL token account holding the collateral, owned by the vault PDA
     pub token_account: Pubkey,
 
     /// Total collateral in vault
@@ -28,9 +37,759 @@ pub struct CollateralVault {
 
     /// PDA bump
     pub bump: u8,
+
+    /// Layout version (0 = legacy account without this field)
+    pub version: u8,
+
+    /// Recorded shortfall not yet covered by insurance or socialization
+    pub bad_debt: u64,
+
+    /// Collateral requested for withdrawal, no longer available
+    pub pending_withdrawal: u64,
+
+    /// Earliest time `claim_withdrawal` may pay out `pending_withdrawal`
+    pub withdrawal_claimable_at: i64,
+
+    /// Set by an authorized risk program to hold the pending withdrawal
+    pub withdrawal_blocked: bool,
+
+    /// Most the owner may pull out over any rolling window (0 = no limit)
+    pub withdrawal_limit: u64,
+
+    /// Length of the withdrawal limit window in seconds
+    pub withdrawal_window: i64,
+
+    /// Start of the current withdrawal window
+    pub window_start: i64,
+
+    /// Owner outflow in the current window. With the part of the previous
+    /// window's outflow that still overlaps the rolling window, it counts
+    /// against both the limit and the guardian's `cosign_threshold`.
+    pub withdrawn_in_window: u64,
+
+    /// Raised limit waiting for `pending_limit_effective_at`
+    pub pending_withdrawal_limit: u64,
+
+    /// Window that goes with `pending_withdrawal_limit`
+    pub pending_withdrawal_window: i64,
+
+    /// When the pending limit applies (0 = none scheduled)
+    pub pending_limit_effective_at: i64,
+
+    /// Once set, `withdraw_to` only pays destinations in the vault's
+    /// `WithdrawAllowlist`. Cannot be turned off.
+    pub allowlist_enabled: bool,
+
+    /// Second signer required for withdrawals above `cosign_threshold`
+    /// (default pubkey = none)
+    pub guardian: Pubkey,
+
+    /// Withdrawals larger than this need the guardian's signature
+    pub cosign_threshold: u64,
+
+    /// Guardian waiting for `guardian_effective_at`
+    pub pending_guardian: Pubkey,
+
+    /// Threshold that goes with `pending_guardian`
+    pub pending_cosign_threshold: u64,
+
+    /// When the pending guardian applies (0 = none scheduled)
+    pub guardian_effective_at: i64,
+
+    /// Collateral allocated to this vault's `SubAccount`s (part of `total_balance`)
+    pub sub_account_balance: u64,
+
+    /// Bumped by `emergency_withdraw`. Lock records and sub-accounts are
+    /// stamped with it; older stamps are stale and carry no balance.
+    pub epoch: u32,
+
+    /// Open `LockRecord`s, sub-account locks included
+    pub lock_count: u32,
+
+    /// Open `SubAccount`s
+    pub sub_account_count: u16,
+
+    /// Open `Delegate`s
+    pub delegate_count: u16,
+
+    /// Part of `withdrawn_in_window` charged by `pending_withdrawal`;
+    /// handed back if the request is cancelled
+    pub pending_in_window: u64,
+
+    /// Owner outflow in the window before `window_start`
+    pub withdrawn_in_prev_window: u64,
+
+    /// Part of `withdrawn_in_prev_window` charged by `pending_withdrawal`
+    pub pending_in_prev_window: u64,
+
+    /// Zeroed space for future fields
+    pub reserved: [u8; 42],
 }
 
 impl CollateralVault {
+    pub const CURRENT_VERSION: u8 = 1;
+
+    pub const LEN: usize =
+        8 +   // discriminator
+        32 +  // owner
+        32 +  // mint
+        32 +  // token_account
+        8 +   // total_balance
+        8 +   // locked_balance
+        8 +   // available_balance
+        8 +   // total_deposited
+        8 +   // total_withdrawn
+        8 +   // created_at
+        1 +   // bump
+        1 +   // version
+        8 +   // bad_debt
+        8 +   // pending_withdrawal
+        8 +   // withdrawal_claimable_at
+        1 +   // withdrawal_blocked
+        8 +   // withdrawal_limit
+        8 +   // withdrawal_window
+        8 +   // window_start
+        8 +   // withdrawn_in_window
+        8 +   // pending_withdrawal_limit
+        8 +   // pending_withdrawal_window
+        8 +   // pending_limit_effective_at
+        1 +   // allowlist_enabled
+        32 +  // guardian
+        8 +   // cosign_threshold
+        32 +  // pending_guardian
+        8 +   // pending_cosign_threshold
+        8 +   // guardian_effective_at
+        8 +   // sub_account_balance
+        4 +   // epoch
+        4 +   // lock_count
+        2 +   // sub_account_count
+        2 +   // delegate_count
+        8 +   // pending_in_window
+        8 +   // withdrawn_in_prev_window
+        8 +   // pending_in_prev_window
+        42;   // reserved
+
+    /// Delay before a looser withdrawal limit takes effect
+    pub const WITHDRAWAL_LIMIT_RAISE_DELAY: i64 = 24 * 60 * 60;
+
+    /// Promotes a scheduled limit raise once its delay has passed.
+    pub fn apply_pending_withdrawal_limit(&mut self, now: i64) {
+        if self.pending_limit_effective_at != 0 && now >= self.pending_limit_effective_at {
+            self.withdrawal_limit = self.pending_withdrawal_limit;
+            self.withdrawal_window = self.pending_withdrawal_window;
+            self.pending_withdrawal_limit = 0;
+            self.pending_withdrawal_window = 0;
+            self.pending_limit_effective_at = 0;
+        }
+    }
+
+    /// Sets the owner's withdrawal limit and returns when it takes effect.
+    /// Tightening applies now (and drops any scheduled raise); anything looser
+    /// waits `WITHDRAWAL_LIMIT_RAISE_DELAY`.
+    pub fn set_withdrawal_limit(&mut self, limit: u64, window: i64, now: i64) -> Result<i64> {
+        require!(limit == 0 || window > 0, VaultError::InvalidWithdrawalLimit);
+
+        self.apply_pending_withdrawal_limit(now);
+
+        let is_raise = match (self.withdrawal_limit, limit) {
+            (0, _) => false,
+            (_, 0) => true,
+            (current, new) => new > current || window < self.withdrawal_window,
+        };
+
+        if is_raise {
+            let effective_at = now
+                .checked_add(Self::WITHDRAWAL_LIMIT_RAISE_DELAY)
+                .ok_or(VaultError::MathOverflow)?;
+            self.pending_withdrawal_limit = limit;
+            self.pending_withdrawal_window = window;
+            self.pending_limit_effective_at = effective_at;
+            return Ok(effective_at);
+        }
+
+        self.withdrawal_limit = limit;
+        self.withdrawal_window = window;
+        self.pending_withdrawal_limit = 0;
+        self.pending_withdrawal_window = 0;
+        self.pending_limit_effective_at = 0;
+        Ok(now)
+    }
+
+    /// Delay before a guardian rotation or removal, or a higher threshold, applies
+    pub const GUARDIAN_ROTATION_DELAY: i64 = 48 * 60 * 60;
+
+    /// Promotes a scheduled guardian change once its delay has passed.
+    pub fn apply_pending_guardian(&mut self, now: i64) {
+        if self.guardian_effective_at != 0 && now >= self.guardian_effective_at {
+            self.guardian = self.pending_guardian;
+            self.cosign_threshold = self.pending_cosign_threshold;
+            self.pending_guardian = Pubkey::default();
+            self.pending_cosign_threshold = 0;
+            self.guardian_effective_at = 0;
+        }
+    }
+
+    /// Sets the co-signing guardian and threshold and returns when they take
+    /// effect. Adding a first guardian or lowering the threshold applies now;
+    /// rotating, removing or raising waits `GUARDIAN_ROTATION_DELAY`.
+    pub fn set_guardian(&mut self, guardian: Pubkey, threshold: u64, now: i64) -> Result<i64> {
+        self.apply_pending_guardian(now);
+
+        let is_tightening = if self.guardian == Pubkey::default() {
+            guardian != Pubkey::default()
+        } else {
+            guardian == self.guardian && threshold <= self.cosign_threshold
+        };
+
+        if !is_tightening {
+            let effective_at = now
+                .checked_add(Self::GUARDIAN_ROTATION_DELAY)
+                .ok_or(VaultError::MathOverflow)?;
+            self.pending_guardian = guardian;
+            self.pending_cosign_threshold = threshold;
+            self.guardian_effective_at = effective_at;
+            return Ok(effective_at);
+        }
+
+        self.guardian = guardian;
+        self.cosign_threshold = threshold;
+        self.pending_guardian = Pubkey::default();
+        self.pending_cosign_threshold = 0;
+        self.guardian_effective_at = 0;
+        Ok(now)
+    }
+
+    /// Outflow window when no withdrawal limit sets one
+    pub const DEFAULT_OUTFLOW_WINDOW: i64 = 24 * 60 * 60;
+
+    /// Moves `window_start` forward to the window containing `now`. The
+    /// window just ended becomes the previous one; anything older is dropped.
+    /// Returns the window length.
+    fn roll_outflow_window(&mut self, now: i64) -> Result<i64> {
+        self.apply_pending_withdrawal_limit(now);
+
+        let window = if self.withdrawal_limit == 0 {
+            Self::DEFAULT_OUTFLOW_WINDOW
+        } else {
+            self.withdrawal_window
+        };
+        if self.window_start == 0 {
+            self.window_start = now;
+            return Ok(window);
+        }
+
+        let windows = now.saturating_sub(self.window_start) / window;
+        if windows > 0 {
+            if windows == 1 {
+                self.withdrawn_in_prev_window = self.withdrawn_in_window;
+                self.pending_in_prev_window = self.pending_in_window;
+            } else {
+                self.withdrawn_in_prev_window = 0;
+                self.pending_in_prev_window = 0;
+            }
+            self.withdrawn_in_window = 0;
+            self.pending_in_window = 0;
+            self.window_start = windows
+                .checked_mul(window)
+                .and_then(|elapsed| self.window_start.checked_add(elapsed))
+                .ok_or(VaultError::MathOverflow)?;
+        }
+
+        Ok(window)
+    }
+
+    /// Owner outflow over the rolling window ending at `now`: this window's
+    /// outflow plus the previous window's, weighted by how much of it the
+    /// rolling window still overlaps. A burst at the end of one window thus
+    /// still counts at the start of the next.
+    fn current_window_outflow(&mut self, now: i64) -> Result<u64> {
+        let window = self.roll_outflow_window(now)?;
+
+        let overlap = window - now.saturating_sub(self.window_start).clamp(0, window);
+        let carried = u128::from(self.withdrawn_in_prev_window)
+            .checked_mul(overlap as u128)
+            .ok_or(VaultError::MathOverflow)?
+            .div_ceil(window as u128);
+
+        u64::try_from(carried)
+            .ok()
+            .and_then(|carried| carried.checked_add(self.withdrawn_in_window))
+            .ok_or(VaultError::MathOverflow.into())
+    }
+
+    /// Fails unless `cosigner` is the vault guardian when `amount`, added to
+    /// this window's earlier outflow, is above the threshold, so splitting a
+    /// withdrawal does not dodge the guardian. Checked next to `record_outflow`.
+    pub fn require_guardian_cosign(
+        &mut self,
+        amount: u64,
+        cosigner: Option<Pubkey>,
+        now: i64,
+    ) -> Result<()> {
+        self.apply_pending_guardian(now);
+
+        if self.guardian == Pubkey::default() {
+            return Ok(());
+        }
+
+        let withdrawn = self
+            .current_window_outflow(now)?
+            .checked_add(amount)
+            .ok_or(VaultError::MathOverflow)?;
+        if withdrawn <= self.cosign_threshold {
+            return Ok(());
+        }
+
+        require!(
+            cosigner == Some(self.guardian),
+            VaultError::GuardianSignatureRequired
+        );
+        Ok(())
+    }
+
+    /// Counts `amount` leaving at the owner's request against the withdrawal
+    /// limit. Every owner outflow path must call this before moving tokens.
+    pub fn record_outflow(&mut self, amount: u64, now: i64) -> Result<()> {
+        let withdrawn = self
+            .current_window_outflow(now)?
+            .checked_add(amount)
+            .ok_or(VaultError::MathOverflow)?;
+        require!(
+            self.withdrawal_limit == 0 || withdrawn <= self.withdrawal_limit,
+            VaultError::WithdrawalLimitExceeded
+        );
+
+        self.withdrawn_in_window = self
+            .withdrawn_in_window
+            .checked_add(amount)
+            .ok_or(VaultError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// `record_outflow` for a withdrawal request, remembering the charge so
+    /// `refund_pending_outflow` can hand it back.
+    pub fn record_pending_outflow(&mut self, amount: u64, now: i64) -> Result<()> {
+        self.record_outflow(amount, now)?;
+        self.pending_in_window = self
+            .pending_in_window
+            .checked_add(amount)
+            .ok_or(VaultError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Takes a cancelled request's charge back out of the windows it was
+    /// counted in. Once those have rolled off there is nothing left to refund.
+    pub fn refund_pending_outflow(&mut self, now: i64) -> Result<()> {
+        self.roll_outflow_window(now)?;
+
+        self.withdrawn_in_window = self
+            .withdrawn_in_window
+            .checked_sub(self.pending_in_window)
+            .ok_or(VaultError::MathOverflow)?;
+        self.withdrawn_in_prev_window = self
+            .withdrawn_in_prev_window
+            .checked_sub(self.pending_in_prev_window)
+            .ok_or(VaultError::MathOverflow)?;
+        self.clear_pending_outflow();
+        Ok(())
+    }
+
+    /// The pending request was paid out or dropped; its charge stays counted
+    /// but can no longer be refunded.
+    pub fn clear_pending_outflow(&mut self) {
+        self.pending_in_window = 0;
+        self.pending_in_prev_window = 0;
+    }
+
+    /// Owner-side checks before an instant withdrawal of `amount` out of the
+    /// available balance: funds, open positions, guardian and limit.
+    pub fn check_instant_withdrawal(
+        &mut self,
+        amount: u64,
+        cosigner: Option<Pubkey>,
+        now: i64,
+    ) -> Result<()> {
+        require!(
+            self.available_balance >= amount,
+            VaultError::InsufficientAvailableBalance
+        );
+
+        if amount == self.total_balance {
+            require!(self.locked_balance == 0, VaultError::OpenPositionsExist);
+        }
+
+        self.require_guardian_cosign(amount, cosigner, now)?;
+        self.record_outflow(amount, now)
+    }
+
+    /// Checks a `withdraw_to` destination wallet. Once the allowlist is on,
+    /// or whenever a delegate withdraws, it must be an active entry.
+    pub fn check_withdraw_destination(
+        &self,
+        allowlist: Option<&WithdrawAllowlist>,
+        destination: &Pubkey,
+        by_delegate: bool,
+        now: i64,
+    ) -> Result<()> {
+        if !self.allowlist_enabled && !by_delegate {
+            return Ok(());
+        }
+
+        let allowlist = allowlist.ok_or(VaultError::AllowlistRequired)?;
+        require!(
+            allowlist.is_allowed(destination, now),
+            VaultError::DestinationNotAllowed
+        );
+        Ok(())
+    }
+
+    /// Books a deposit of `amount` of which `received` reached the vault's
+    /// token account, and checks the protocol caps against the new balances.
+    /// Who funded it makes no difference to the ledger.
+    pub fn credit_deposit(
+        &mut self,
+        tvl: &mut TvlCounter,
+        config: &GlobalConfig,
+        amount: u64,
+        received: u64,
+    ) -> Result<()> {
+        require!(received > 0, VaultError::InvalidAmount);
+
+        let total_value_locked = tvl
+            .total_value_locked
+            .checked_add(received)
+            .ok_or(VaultError::MathOverflow)?;
+        let total_balance = self
+            .total_balance
+            .checked_add(received)
+            .ok_or(VaultError::MathOverflow)?;
+
+        config.check_deposit_caps(amount, total_balance, total_value_locked)?;
+
+        self.available_balance = self
+            .available_balance
+            .checked_add(received)
+            .ok_or(VaultError::MathOverflow)?;
+
+        self.total_deposited = self
+            .total_deposited
+            .checked_add(received)
+            .ok_or(VaultError::MathOverflow)?;
+
+        self.total_balance = total_balance;
+        tvl.total_value_locked = total_value_locked;
+        Ok(())
+    }
+
+    /// Books `debited` tokens that left the vault out of the available balance.
+    pub fn debit_withdrawal(&mut self, tvl: &mut TvlCounter, debited: u64) -> Result<()> {
+        self.total_balance = self
+            .total_balance
+            .checked_sub(debited)
+            .ok_or(VaultError::MathOverflow)?;
+
+        self.available_balance = self
+            .available_balance
+            .checked_sub(debited)
+            .ok_or(VaultError::MathOverflow)?;
+
+        self.total_withdrawn = self
+            .total_withdrawn
+            .checked_add(debited)
+            .ok_or(VaultError::MathOverflow)?;
+
+        tvl.sub(debited)
+    }
+
+    /// Takes `amount` out of one position's locked collateral, held by the
+    /// vault itself or by `sub_account`. `total_balance` is left to the caller.
+    fn debit_position(
+        &mut self,
+        lock_record: &mut LockRecord,
+        sub_account: Option<&mut SubAccount>,
+        amount: u64,
+    ) -> Result<()> {
+        self.require_current_lock(lock_record)?;
+
+        require!(
+            lock_record.amount >= amount,
+            VaultError::InsufficientLockedBalance
+        );
+
+        match sub_account {
+            Some(sub_account) => {
+                self.require_current_sub_account(sub_account)?;
+
+                require!(
+                    sub_account.locked_balance >= amount,
+                    VaultError::InsufficientLockedBalance
+                );
+
+                sub_account.locked_balance = sub_account
+                    .locked_balance
+                    .checked_sub(amount)
+                    .ok_or(VaultError::MathOverflow)?;
+
+                sub_account.total_balance = sub_account
+                    .total_balance
+                    .checked_sub(amount)
+                    .ok_or(VaultError::MathOverflow)?;
+
+                self.sub_account_balance = self
+                    .sub_account_balance
+                    .checked_sub(amount)
+                    .ok_or(VaultError::MathOverflow)?;
+            }
+            None => {
+                require!(
+                    self.locked_balance >= amount,
+                    VaultError::InsufficientLockedBalance
+                );
+
+                self.locked_balance = self
+                    .locked_balance
+                    .checked_sub(amount)
+                    .ok_or(VaultError::MathOverflow)?;
+            }
+        }
+
+        lock_record.amount = lock_record
+            .amount
+            .checked_sub(amount)
+            .ok_or(VaultError::MathOverflow)?;
+
+        Ok(())
+    }
+
+    /// Takes `amount` out of the balance `mode` names for `transfer_collateral`,
+    /// on the vault itself or on `sub_account`, and returns the position it
+    /// settles, if any. `total_balance` is debited once the tokens have moved.
+    pub fn debit_transfer(
+        &mut self,
+        mode: TransferMode,
+        amount: u64,
+        lock_record: Option<&mut LockRecord>,
+        sub_account: Option<&mut SubAccount>,
+    ) -> Result<Option<u64>> {
+        match mode {
+            TransferMode::Available => {
+                match sub_account {
+                    Some(sub_account) => {
+                        self.require_current_sub_account(sub_account)?;
+
+                        require!(
+                            sub_account.available_balance >= amount,
+                            VaultError::InsufficientAvailableBalance
+                        );
+
+                        sub_account.available_balance = sub_account
+                            .available_balance
+                            .checked_sub(amount)
+                            .ok_or(VaultError::MathOverflow)?;
+
+                        sub_account.total_balance = sub_account
+                            .total_balance
+                            .checked_sub(amount)
+                            .ok_or(VaultError::MathOverflow)?;
+
+                        self.sub_account_balance = self
+                            .sub_account_balance
+                            .checked_sub(amount)
+                            .ok_or(VaultError::MathOverflow)?;
+                    }
+                    None => {
+                        require!(
+                            self.available_balance >= amount,
+                            VaultError::InsufficientAvailableBalance
+                        );
+
+                        self.available_balance = self
+                            .available_balance
+                            .checked_sub(amount)
+                            .ok_or(VaultError::MathOverflow)?;
+                    }
+                }
+
+                Ok(None)
+            }
+            TransferMode::Locked => {
+                let lock_record = lock_record.ok_or(VaultError::LockRecordRequired)?;
+
+                self.debit_position(lock_record, sub_account, amount)?;
+
+                Ok(Some(lock_record.position_id))
+            }
+        }
+    }
+
+    /// Books the token movement of a `transfer_collateral`: this vault lost
+    /// `amount`, `to` gained the `received` part of it and becomes free to
+    /// withdraw it. A fee withheld in between leaves TVL.
+    pub fn settle_transfer(
+        &mut self,
+        to: &mut CollateralVault,
+        tvl: &mut TvlCounter,
+        amount: u64,
+        received: u64,
+    ) -> Result<()> {
+        let fee = amount.checked_sub(received).ok_or(VaultError::MathOverflow)?;
+
+        self.total_balance = self
+            .total_balance
+            .checked_sub(amount)
+            .ok_or(VaultError::MathOverflow)?;
+
+        to.total_balance = to
+            .total_balance
+            .checked_add(received)
+            .ok_or(VaultError::MathOverflow)?;
+
+        to.available_balance = to
+            .available_balance
+            .checked_add(received)
+            .ok_or(VaultError::MathOverflow)?;
+
+        tvl.sub(fee)
+    }
+
+    /// Books `seized` tokens taken out of one position's locked collateral,
+    /// on the vault or on `sub_account`, by `liquidate`. The vault loses the gross amount, whatever part of it
+    /// Token-2022 withholds on the way to the liquidator or the fund.
+    pub fn seize_locked(
+        &mut self,
+        lock_record: &mut LockRecord,
+        sub_account: Option<&mut SubAccount>,
+        tvl: &mut TvlCounter,
+        seized: u64,
+    ) -> Result<()> {
+        self.debit_position(lock_record, sub_account, seized)?;
+
+        self.total_balance = self
+            .total_balance
+            .checked_sub(seized)
+            .ok_or(VaultError::MathOverflow)?;
+
+        tvl.sub(seized)
+    }
+
+    /// Uncounts a lock record that is about to close, on `sub_account` too
+    /// when the lock is keyed by it.
+    pub fn untrack_lock(&mut self, sub_account: Option<&mut SubAccount>) -> Result<()> {
+        if let Some(sub_account) = sub_account {
+            sub_account.lock_count = sub_account
+                .lock_count
+                .checked_sub(1)
+                .ok_or(VaultError::MathOverflow)?;
+        }
+        self.lock_count = self
+            .lock_count
+            .checked_sub(1)
+            .ok_or(VaultError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// `close_vault` precondition: nothing on the ledger and no debt that
+    /// closing would erase.
+    pub fn check_closable(&self) -> Result<()> {
+        require!(
+            self.total_balance == 0 && self.locked_balance == 0,
+            VaultError::VaultNotEmpty
+        );
+        require!(self.bad_debt == 0, VaultError::OutstandingBadDebt);
+        require!(
+            self.lock_count == 0 && self.sub_account_count == 0 && self.delegate_count == 0,
+            VaultError::OpenVaultRecords
+        );
+        Ok(())
+    }
+
+    /// Emergency exit paid out everything: strand every record stamped so far.
+    /// Books an emergency withdrawal of `debited`: the ledger empties and a
+    /// new epoch starts. The outflow window is left alone on purpose.
+    pub fn book_emergency_withdrawal(&mut self, debited: u64) -> Result<()> {
+        self.total_balance = self
+            .total_balance
+            .checked_sub(debited)
+            .ok_or(VaultError::MathOverflow)?;
+        self.total_withdrawn = self
+            .total_withdrawn
+            .checked_add(debited)
+            .ok_or(VaultError::MathOverflow)?;
+
+        self.available_balance = 0;
+        self.locked_balance = 0;
+        self.pending_withdrawal = 0;
+        self.clear_pending_outflow();
+        self.sub_account_balance = 0;
+        self.withdrawal_claimable_at = 0;
+        self.start_new_epoch()
+    }
+
+    pub fn start_new_epoch(&mut self) -> Result<()> {
+        self.epoch = self.epoch.checked_add(1).ok_or(VaultError::MathOverflow)?;
+        Ok(())
+    }
+
+    pub fn require_current_lock(&self, lock_record: &LockRecord) -> Result<()> {
+        require!(lock_record.epoch == self.epoch, VaultError::StaleLockRecord);
+        Ok(())
+    }
+
+    pub fn require_current_sub_account(&self, sub_account: &SubAccount) -> Result<()> {
+        require!(sub_account.epoch == self.epoch, VaultError::StaleSubAccount);
+        Ok(())
+    }
+
+    /// Point-in-time view of the vault for `get_vault_state`.
+    pub fn snapshot(&self, vault: Pubkey) -> VaultStateSnapshot {
+        let mut flags = 0;
+        if self.withdrawal_blocked {
+            flags |= VaultStateSnapshot::FLAG_WITHDRAWAL_BLOCKED;
+        }
+        if self.allowlist_enabled {
+            flags |= VaultStateSnapshot::FLAG_ALLOWLIST_ENABLED;
+        }
+        if self.guardian != Pubkey::default() {
+            flags |= VaultStateSnapshot::FLAG_GUARDIAN_SET;
+        }
+        if self.bad_debt > 0 {
+            flags |= VaultStateSnapshot::FLAG_BAD_DEBT;
+        }
+
+        VaultStateSnapshot {
+            snapshot_version: VaultStateSnapshot::VERSION,
+            vault,
+            owner: self.owner,
+            mint: self.mint,
+            total_balance: self.total_balance,
+            locked_balance: self.locked_balance,
+            available_balance: self.available_balance,
+            sub_account_balance: self.sub_account_balance,
+            pending_withdrawal: self.pending_withdrawal,
+            withdrawal_claimable_at: self.withdrawal_claimable_at,
+            bad_debt: self.bad_debt,
+            flags,
+        }
+    }
+}
+
+/// Vault layout from before per-mint vaults and `version`, at PDA
+/// `["vault", owner]`. It shares `CollateralVault`'s discriminator; only
+/// `migrate_vault` reads it.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, Debug)]
+pub struct LegacyCollateralVault {
+    pub owner: Pubkey,
+    pub token_account: Pubkey,
+    pub total_balance: u64,
+    pub locked_balance: u64,
+    pub available_balance: u64,
+    pub total_deposited: u64,
+    pub total_withdrawn: u64,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl LegacyCollateralVault {
     pub const LEN: usize =
         8 +   // discriminator
         32 +  // owner
@@ -42,19 +801,689 @@ impl CollateralVault {
         8 +   // total_withdrawn
         8 +   // created_at
         1;    // bump
+
+    /// How long migrated legacy locks stay locked before anyone may release them
+    pub const LOCK_GRACE_PERIOD: i64 = 7 * 24 * 60 * 60;
+
+    /// Parses a legacy vault account, discriminator included.
+    pub fn try_from_account_data(data: &[u8]) -> Result<Self> {
+        require!(
+            data.len() == Self::LEN && data[..8] == *CollateralVault::DISCRIMINATOR,
+            VaultError::InvalidVaultAccount
+        );
+        Self::deserialize(&mut &data[8..]).map_err(|_| VaultError::InvalidVaultAccount.into())
+    }
+
+    /// `(available, locked)` to carry over when `received` of the legacy
+    /// token account's balance reached the new vault. Tokens beyond the ledger
+    /// stay untracked; a transfer-fee shortfall comes out of available first.
+    pub fn carried_balances(&self, received: u64) -> Result<(u64, u64)> {
+        let shortfall = self.total_balance.saturating_sub(received);
+        let from_available = shortfall.min(self.available_balance);
+
+        let available = self.available_balance - from_available;
+        let locked = self
+            .locked_balance
+            .checked_sub(shortfall - from_available)
+            .ok_or(VaultError::MathOverflow)?;
+
+        Ok((available, locked))
+    }
+}
+
+/// Return data of `get_vault_state`. Callers decode this instead of the
+/// `CollateralVault` account layout. New fields are only ever appended and
+/// bump `VERSION`, so a v1 reader can decode any later snapshot's prefix.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct VaultStateSnapshot {
+    pub snapshot_version: u8,
+    pub vault: Pubkey,
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub total_balance: u64,
+    pub locked_balance: u64,
+    pub available_balance: u64,
+    pub sub_account_balance: u64,
+    pub pending_withdrawal: u64,
+    pub withdrawal_claimable_at: i64,
+    pub bad_debt: u64,
+    pub flags: u8,
+}
+
+impl VaultStateSnapshot {
+    pub const VERSION: u8 = 1;
+
+    pub const FLAG_WITHDRAWAL_BLOCKED: u8 = 1 << 0;
+    pub const FLAG_ALLOWLIST_ENABLED: u8 = 1 << 1;
+    pub const FLAG_GUARDIAN_SET: u8 = 1 << 2;
+    pub const FLAG_BAD_DEBT: u8 = 1 << 3;
+
+    pub fn has_flag(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+
+    /// Decodes a snapshot of this or any later version, ignoring fields
+    /// appended after the ones known here.
+    pub fn decode(mut data: &[u8]) -> Result<Self> {
+        let snapshot =
+            Self::deserialize(&mut data).map_err(|_| VaultError::InvalidVaultStateData)?;
+        require!(
+            snapshot.snapshot_version >= Self::VERSION,
+            VaultError::InvalidVaultStateData
+        );
+        Ok(snapshot)
+    }
 }
 
+/// Which balance `transfer_collateral` draws from on the sending vault.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TransferMode {
+    /// Free collateral from `available_balance`
+    Available,
+    /// Collateral locked for a position, e.g. settling a losing position
+    Locked,
+}
+
+/// Collateral locked by one caller program for one position.
+/// PDA: `["lock", vault, caller_program, position_id]`. For sub-account locks
+/// the sub-account takes the place of the vault in both seeds and `vault`.
+#[account]
+pub struct LockRecord {
+    /// Vault the collateral is locked in
+    pub vault: Pubkey,
+
+    /// Program that locked the collateral; only it can unlock
+    pub caller_program: Pubkey,
+
+    /// Position identifier chosen by the caller program
+    pub position_id: u64,
+
+    /// Collateral currently locked for this position
+    pub amount: u64,
+
+    /// Account that paid the rent; refunded when the record is closed
+    pub rent_payer: Pubkey,
+
+    /// Record creation timestamp
+    pub created_at: i64,
+
+    /// After this time anyone may release the lock (0 = never expires)
+    pub expires_at: i64,
+
+    /// PDA bump
+    pub bump: u8,
+
+    /// `CollateralVault::epoch` when the lock was taken
+    pub epoch: u32,
+}
+
+impl LockRecord {
+    /// Caller of the record that parks a migrated vault's legacy locks.
+    /// No program can sign for it, so only expiry releases the record.
+    pub const LEGACY_CALLER: Pubkey = Pubkey::new_from_array([0; 32]);
+    pub const LEGACY_POSITION_ID: u64 = 0;
+
+    pub const LEN: usize =
+        8 +   // discriminator
+        32 +  // vault
+        32 +  // caller_program
+        8 +   // position_id
+        8 +   // amount
+        32 +  // rent_payer
+        8 +   // created_at
+        8 +   // expires_at
+        1 +   // bump
+        4;    // epoch
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at != 0 && now >= self.expires_at
+    }
+}
+
+/// One allowed `withdraw_to` destination.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AllowlistEntry {
+    /// Wallet that must own the destination token account
+    pub destination: Pubkey,
+
+    /// Entry is usable from this time on
+    pub active_at: i64,
+}
+
+/// Owner-managed `withdraw_to` destinations. PDA: `["withdraw_allowlist", vault]`.
+#[account]
+pub struct WithdrawAllowlist {
+    /// Vault this allowlist guards
+    pub vault: Pubkey,
+
+    pub entries: Vec<AllowlistEntry>,
+
+    /// PDA bump
+    pub bump: u8,
+}
+
+impl WithdrawAllowlist {
+    pub const MAX_ENTRIES: usize = 8;
+
+    /// Delay before a newly added destination can receive funds
+    pub const ACTIVATION_DELAY: i64 = 48 * 60 * 60;
+
+    pub const LEN: usize =
+        8 +   // discriminator
+        32 +  // vault
+        4 +   // vec length
+        ((32 + 8) * Self::MAX_ENTRIES) + // entries
+        1;    // bump
+
+    pub fn is_allowed(&self, destination: &Pubkey, now: i64) -> bool {
+        self.entries
+            .iter()
+            .any(|e| e.destination == *destination && now >= e.active_at)
+    }
+
+    /// Adds `destination`, usable after `ACTIVATION_DELAY`. Returns the activation time.
+    pub fn add_destination(&mut self, destination: Pubkey, now: i64) -> Result<i64> {
+        require!(
+            !self.entries.iter().any(|e| e.destination == destination),
+            VaultError::DestinationAlreadyAllowed
+        );
+        require!(
+            self.entries.len() < Self::MAX_ENTRIES,
+            VaultError::TooManyDestinations
+        );
+
+        let active_at = now
+            .checked_add(Self::ACTIVATION_DELAY)
+            .ok_or(VaultError::MathOverflow)?;
+        self.entries.push(AllowlistEntry { destination, active_at });
+        Ok(active_at)
+    }
+
+    pub fn remove_destination(&mut self, destination: &Pubkey) -> Result<()> {
+        let index = self
+            .entries
+            .iter()
+            .position(|e| e.destination == *destination)
+            .ok_or(VaultError::DestinationNotAllowed)?;
+
+        self.entries.swap_remove(index);
+        Ok(())
+    }
+}
+
+/// Isolated margin bucket inside a vault.
+/// PDA: `["sub_account", vault, index]` (index as little-endian `u16`).
+///
+/// Funds move in from the vault's available balance and back out the same
+/// way; tokens never leave the vault token account.
+#[account]
+pub struct SubAccount {
+    /// Parent vault
+    pub vault: Pubkey,
+
+    /// Owner-chosen index
+    pub index: u16,
+
+    /// Collateral allocated to this sub-account
+    pub total_balance: u64,
+
+    /// Collateral locked for positions
+    pub locked_balance: u64,
+
+    /// Collateral free to lock or move
+    pub available_balance: u64,
+
+    /// Creation timestamp
+    pub created_at: i64,
+
+    /// PDA bump
+    pub bump: u8,
+
+    /// `CollateralVault::epoch` when the sub-account was created
+    pub epoch: u32,
+
+    /// Open `LockRecord`s keyed by this sub-account
+    pub lock_count: u32,
+}
+
+impl SubAccount {
+    pub const LEN: usize =
+        8 +   // discriminator
+        32 +  // vault
+        2 +   // index
+        8 +   // total_balance
+        8 +   // locked_balance
+        8 +   // available_balance
+        8 +   // created_at
+        1 +   // bump
+        4 +   // epoch
+        4;    // lock_count
+}
+
+/// Session key the vault owner lets act on the vault.
+/// PDA: `["delegate", vault, delegate]`.
+#[account]
+pub struct Delegate {
+    /// Vault the delegate acts on
+    pub vault: Pubkey,
+
+    /// Session key that signs in place of the owner
+    pub delegate: Pubkey,
+
+    /// Bitmask of `Delegate::PERMISSION_*`
+    pub permissions: u8,
+
+    /// Most the delegate may withdraw over its lifetime
+    pub withdraw_cap: u64,
+
+    /// Amount withdrawn so far
+    pub withdrawn: u64,
+
+    /// Delegate stops working at this time
+    pub expires_at: i64,
+
+    /// PDA bump
+    pub bump: u8,
+}
+
+impl Delegate {
+    pub const PERMISSION_DEPOSIT: u8 = 1 << 0;
+    pub const PERMISSION_WITHDRAW: u8 = 1 << 1;
+
+    pub const PERMISSION_ALL: u8 = Self::PERMISSION_DEPOSIT | Self::PERMISSION_WITHDRAW;
+
+    pub const LEN: usize =
+        8 +   // discriminator
+        32 +  // vault
+        32 +  // delegate
+        1 +   // permissions
+        8 +   // withdraw_cap
+        8 +   // withdrawn
+        8 +   // expires_at
+        1;    // bump
+
+    pub fn authorize(&self, permission: u8, now: i64) -> Result<()> {
+        require!(now < self.expires_at, VaultError::DelegateExpired);
+        require!(
+            self.permissions & permission != 0,
+            VaultError::DelegatePermissionDenied
+        );
+        Ok(())
+    }
+
+    pub fn record_withdrawal(&mut self, amount: u64) -> Result<()> {
+        let withdrawn = self
+            .withdrawn
+            .checked_add(amount)
+            .ok_or(VaultError::MathOverflow)?;
+        require!(
+            withdrawn <= self.withdraw_cap,
+            VaultError::DelegateCapExceeded
+        );
+
+        self.withdrawn = withdrawn;
+        Ok(())
+    }
+}
+
+/// How a collateral mint is priced. PDA: `["collateral_config", mint]`.
+#[account]
+pub struct CollateralConfig {
+    /// Mint this config prices
+    pub mint: Pubkey,
+
+    /// Pyth-compatible price account for the mint
+    pub oracle: Pubkey,
+
+    /// Share of the oracle value ignored for margin, in basis points
+    pub haircut_bps: u16,
+
+    /// Oldest acceptable oracle publish time, in seconds before now
+    pub max_staleness: i64,
+
+    /// PDA bump
+    pub bump: u8,
+}
+
+impl CollateralConfig {
+    pub const LEN: usize =
+        8 +   // discriminator
+        32 +  // mint
+        32 +  // oracle
+        2 +   // haircut_bps
+        8 +   // max_staleness
+        1;    // bump
+
+    pub fn check_fresh(&self, publish_time: i64, now: i64) -> Result<()> {
+        require!(
+            now.saturating_sub(publish_time) <= self.max_staleness,
+            VaultError::StaleOraclePrice
+        );
+        Ok(())
+    }
+}
+
+/// USD valuation returned by `value_vault`. Values carry
+/// `oracle::USD_DECIMALS` decimals and already have the haircut applied.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct VaultValuation {
+    pub vault: Pubkey,
+    pub mint: Pubkey,
+    pub total_value: u64,
+    pub available_value: u64,
+    pub locked_value: u64,
+    pub price: i64,
+    pub expo: i32,
+    pub publish_time: i64,
+    pub haircut_bps: u16,
+}
+
+/// Net user collateral held across all vaults of one mint. PDA: `["tvl", mint]`.
+///
+/// Every path that moves tokens into or out of vault ledgers updates it with
+/// what actually moved, so Token-2022 fees withheld between two vaults are
+/// subtracted too. It errors rather than clamps on underflow; `reconcile_tvl`
+/// corrects a counter that predates full tracking, once.
+#[account]
+pub struct TvlCounter {
+    /// Collateral mint being counted
+    pub mint: Pubkey,
+
+    /// Net deposited collateral
+    pub total_value_locked: u64,
+
+    /// PDA bump
+    pub bump: u8,
+
+    /// Set by the one-time `reconcile_tvl`
+    pub reconciled: bool,
+}
+
+impl TvlCounter {
+    pub const LEN: usize =
+        8 +   // discriminator
+        32 +  // mint
+        8 +   // total_value_locked
+        1 +   // bump
+        1;    // reconciled
+
+    /// Counts collateral that reached a vault.
+    pub fn add(&mut self, amount: u64) -> Result<()> {
+        self.total_value_locked = self
+            .total_value_locked
+            .checked_add(amount)
+            .ok_or(VaultError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Uncounts collateral that left the vaults.
+    pub fn sub(&mut self, amount: u64) -> Result<()> {
+        self.total_value_locked = self
+            .total_value_locked
+            .checked_sub(amount)
+            .ok_or(VaultError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Uncounts collateral paid out by `emergency_withdraw`, which must not
+    /// revert on drift: an undercounting counter bottoms out at zero.
+    pub fn sub_saturating(&mut self, amount: u64) {
+        self.total_value_locked = self.total_value_locked.saturating_sub(amount);
+    }
+}
+
+/// Protocol-owned collateral for one mint. PDA: `["insurance_fund", mint]`.
+#[account]
+pub struct InsuranceFund {
+    /// Collateral mint held by the fund
+    pub mint: Pubkey,
+
+    /// Token account holding the fund, owned by this PDA
+    pub token_account: Pubkey,
+
+    /// Collateral currently in the fund
+    pub balance: u64,
+
+    /// Lifetime fees, penalties and contributions received
+    pub total_received: u64,
+
+    /// Lifetime amount drawn to cover losses
+    pub total_drawn: u64,
+
+    /// Fund creation timestamp
+    pub created_at: i64,
+
+    /// PDA bump
+    pub bump: u8,
+}
+
+impl InsuranceFund {
+    pub const LEN: usize =
+        8 +   // discriminator
+        32 +  // mint
+        32 +  // token_account
+        8 +   // balance
+        8 +   // total_received
+        8 +   // total_drawn
+        8 +   // created_at
+        1;    // bump
+
+    /// Books `received` tokens that reached the fund's token account.
+    pub fn credit(&mut self, received: u64) -> Result<()> {
+        self.balance = self
+            .balance
+            .checked_add(received)
+            .ok_or(VaultError::MathOverflow)?;
+        self.total_received = self
+            .total_received
+            .checked_add(received)
+            .ok_or(VaultError::MathOverflow)?;
+        Ok(())
+    }
+}
+
+/// Why an authorized program drew from the insurance fund.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InsuranceDrawReason {
+    /// Cover a vault's unrecoverable deficit
+    BadDebt,
+    /// Cover a liquidation that did not fully repay a position
+    LiquidationShortfall,
+    /// Anything else; see the caller program's own logs
+    Other,
+}
+
+/// Protocol-wide switches. PDA: `["global_config"]`.
+#[account]
+pub struct GlobalConfig {
+    /// Key that can pause and unpause alongside the `VaultAuthority` admin
+    pub guardian: Pubkey,
+
+    /// Bitmask of paused instruction types (`GlobalConfig::PAUSE_*`)
+    pub paused: u32,
+
+    /// Seconds between `request_withdrawal` and `claim_withdrawal`.
+    /// While non-zero, instant `withdraw` is disabled.
+    pub withdrawal_cooldown: i64,
+
+    /// Largest single deposit (0 = no limit)
+    pub max_deposit_per_tx: u64,
+
+    /// Largest `total_balance` a deposit may leave in a vault (0 = no limit)
+    pub max_vault_balance: u64,
+
+    /// Largest per-mint `TvlCounter::total_value_locked` (0 = no limit)
+    pub max_tvl: u64,
+
+    /// Set by the admin to open `emergency_withdraw`
+    pub emergency_mode: bool,
+
+    /// Seconds without authorized-program activity after which emergency
+    /// mode turns on by itself (0 = never)
+    pub emergency_inactivity_period: i64,
+
+    /// Last instruction from an authorized program: lock, unlock, transfer,
+    /// liquidation, withdrawal block, insurance draw or bad-debt handling
+    pub last_authorized_activity: i64,
+
+    /// PDA bump
+    pub bump: u8,
+}
+
+impl GlobalConfig {
+    pub const PAUSE_INITIALIZE: u32 = 1 << 0;
+    pub const PAUSE_DEPOSIT: u32 = 1 << 1;
+    pub const PAUSE_WITHDRAW: u32 = 1 << 2;
+    pub const PAUSE_LOCK: u32 = 1 << 3;
+    pub const PAUSE_UNLOCK: u32 = 1 << 4;
+    pub const PAUSE_TRANSFER: u32 = 1 << 5;
+    pub const PAUSE_CLOSE: u32 = 1 << 6;
+    pub const PAUSE_LIQUIDATE: u32 = 1 << 7;
+    pub const PAUSE_INSURANCE: u32 = 1 << 8;
+    pub const PAUSE_BAD_DEBT: u32 = 1 << 9;
+    pub const PAUSE_SUB_ACCOUNT: u32 = 1 << 10;
+    /// Owner, risk-program and admin settings changes
+    pub const PAUSE_SETTINGS: u32 = 1 << 11;
+
+    pub const PAUSE_ALL: u32 = Self::PAUSE_INITIALIZE
+        | Self::PAUSE_DEPOSIT
+        | Self::PAUSE_WITHDRAW
+        | Self::PAUSE_LOCK
+        | Self::PAUSE_UNLOCK
+        | Self::PAUSE_TRANSFER
+        | Self::PAUSE_CLOSE
+        | Self::PAUSE_LIQUIDATE
+        | Self::PAUSE_INSURANCE
+        | Self::PAUSE_BAD_DEBT
+        | Self::PAUSE_SUB_ACCOUNT
+        | Self::PAUSE_SETTINGS;
+
+    pub const LEN: usize =
+        8 +   // discriminator
+        32 +  // guardian
+        4 +   // paused
+        8 +   // withdrawal_cooldown
+        8 +   // max_deposit_per_tx
+        8 +   // max_vault_balance
+        8 +   // max_tvl
+        1 +   // emergency_mode
+        8 +   // emergency_inactivity_period
+        8 +   // last_authorized_activity
+        1;    // bump
+
+    pub fn is_paused(&self, flag: u32) -> bool {
+        self.paused & flag != 0
+    }
+
+    /// Emergency exits are open if the admin says so or authorized programs
+    /// have gone quiet for longer than `emergency_inactivity_period`.
+    pub fn is_emergency(&self, now: i64) -> bool {
+        self.emergency_mode
+            || (self.emergency_inactivity_period > 0
+                && now.saturating_sub(self.last_authorized_activity)
+                    >= self.emergency_inactivity_period)
+    }
+
+    pub fn record_authorized_activity(&mut self, now: i64) {
+        self.last_authorized_activity = now;
+    }
+
+    /// Checks a deposit of `amount` that leaves the vault at `new_vault_balance`
+    /// and the mint's TVL at `new_tvl` against the configured caps.
+    pub fn check_deposit_caps(&self, amount: u64, new_vault_balance: u64, new_tvl: u64) -> Result<()> {
+        require!(
+            self.max_deposit_per_tx == 0 || amount <= self.max_deposit_per_tx,
+            VaultError::DepositTooLarge
+        );
+        require!(
+            self.max_vault_balance == 0 || new_vault_balance <= self.max_vault_balance,
+            VaultError::VaultCapExceeded
+        );
+        require!(
+            self.max_tvl == 0 || new_tvl <= self.max_tvl,
+            VaultError::TvlCapExceeded
+        );
+        Ok(())
+    }
+}
 
 #[account]
 pub struct VaultAuthority {
+    /// Key allowed to change the whitelist
+    pub admin: Pubkey,
+
+    /// Admin proposed by `propose_admin`, waiting for `accept_admin`
+    pub pending_admin: Option<Pubkey>,
+
     pub authorized_programs: Vec<Pubkey>,
     pub bump: u8,
 }
 
 impl VaultAuthority {
+    pub const MAX_AUTHORIZED_PROGRAMS: usize = 16;
+
+    /// Space of a `LegacyVaultAuthority` account
+    pub const LEGACY_LEN: usize =
+        8 +   // discriminator
+        4 +   // vec length
+        (32 * Self::MAX_AUTHORIZED_PROGRAMS) +
+        1;    // bump
+
     pub const LEN: usize =
         8 +   // discriminator
+        32 +  // admin
+        1 + 32 + // pending_admin
         4 +   // vec length
-        (32 * 16) + // up to 16 authorized programs (reasonable cap)
+        (32 * Self::MAX_AUTHORIZED_PROGRAMS) + // up to 16 authorized programs (reasonable cap)
         1;    // bump
+
+    pub fn is_authorized(&self, program: &Pubkey) -> bool {
+        self.authorized_programs.contains(program)
+    }
+
+    pub fn add_program(&mut self, program: Pubkey) -> Result<()> {
+        require!(
+            !self.is_authorized(&program),
+            VaultError::ProgramAlreadyAuthorized
+        );
+        require!(
+            self.authorized_programs.len() < Self::MAX_AUTHORIZED_PROGRAMS,
+            VaultError::TooManyAuthorizedPrograms
+        );
+
+        self.authorized_programs.push(program);
+        Ok(())
+    }
+
+    pub fn remove_program(&mut self, program: &Pubkey) -> Result<()> {
+        let index = self
+            .authorized_programs
+            .iter()
+            .position(|p| p == program)
+            .ok_or(VaultError::ProgramNotAuthorized)?;
+
+        self.authorized_programs.swap_remove(index);
+        Ok(())
+    }
+}
+
+/// Whitelist layout from before `admin` and `pending_admin`. It shares
+/// `VaultAuthority`'s discriminator; only `migrate_vault_authority` reads it.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, Debug)]
+pub struct LegacyVaultAuthority {
+    pub authorized_programs: Vec<Pubkey>,
+    pub bump: u8,
+}
+
+impl LegacyVaultAuthority {
+    /// Parses a legacy whitelist account, discriminator included.
+    pub fn try_from_account_data(data: &[u8]) -> Result<Self> {
+        require!(
+            data.len() == VaultAuthority::LEGACY_LEN
+                && data[..8] == *VaultAuthority::DISCRIMINATOR,
+            VaultError::InvalidVaultAuthorityAccount
+        );
+        Self::deserialize(&mut &data[8..])
+            .map_err(|_| VaultError::InvalidVaultAuthorityAccount.into())
+    }
 }
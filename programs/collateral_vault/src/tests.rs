@@ -4,6 +4,7 @@ use anchor_lang::prelude::*;
 fn fresh_vault(owner: Pubkey) -> CollateralVault {
     CollateralVault {
         owner,
+        mint: Pubkey::new_unique(),
         token_account: Pubkey::new_unique(),
         total_balance: 0,
         available_balance: 0,
@@ -12,6 +13,33 @@ fn fresh_vault(owner: Pubkey) -> CollateralVault {
         total_withdrawn: 0,
         created_at: 0,
         bump: 0,
+        version: CollateralVault::CURRENT_VERSION,
+        bad_debt: 0,
+        pending_withdrawal: 0,
+        withdrawal_claimable_at: 0,
+        withdrawal_blocked: false,
+        withdrawal_limit: 0,
+        withdrawal_window: 0,
+        window_start: 0,
+        withdrawn_in_window: 0,
+        pending_withdrawal_limit: 0,
+        pending_withdrawal_window: 0,
+        pending_limit_effective_at: 0,
+        allowlist_enabled: false,
+        guardian: Pubkey::default(),
+        cosign_threshold: 0,
+        pending_guardian: Pubkey::default(),
+        pending_cosign_threshold: 0,
+        guardian_effective_at: 0,
+        sub_account_balance: 0,
+        epoch: 0,
+        lock_count: 0,
+        sub_account_count: 0,
+        delegate_count: 0,
+        pending_in_window: 0,
+        withdrawn_in_prev_window: 0,
+        pending_in_prev_window: 0,
+        reserved: [0; 42],
     }
 }
 
@@ -78,3 +106,1250 @@ fn lock_overflow() {
 fn unauthorized_not_tested_here() {
     // CPI auth is hard to unit test so we will rely on integration tests in TS
 }
+
+fn fresh_authority(admin: Pubkey) -> VaultAuthority {
+    VaultAuthority {
+        admin,
+        pending_admin: None,
+        authorized_programs: vec![],
+        bump: 0,
+    }
+}
+
+#[test]
+fn authority_add_and_remove_programs() {
+    let mut authority = fresh_authority(Pubkey::new_unique());
+    let perp = Pubkey::new_unique();
+    let perp_v2 = Pubkey::new_unique();
+
+    authority.add_program(perp).unwrap();
+    authority.add_program(perp_v2).unwrap();
+    assert!(authority.is_authorized(&perp));
+
+    assert_eq!(
+        authority.add_program(perp).unwrap_err(),
+        VaultError::ProgramAlreadyAuthorized.into()
+    );
+
+    authority.remove_program(&perp).unwrap();
+    assert!(!authority.is_authorized(&perp));
+    assert!(authority.is_authorized(&perp_v2));
+
+    assert_eq!(
+        authority.remove_program(&perp).unwrap_err(),
+        VaultError::ProgramNotAuthorized.into()
+    );
+}
+
+#[test]
+fn authority_whitelist_is_capped() {
+    let mut authority = fresh_authority(Pubkey::new_unique());
+
+    for _ in 0..VaultAuthority::MAX_AUTHORIZED_PROGRAMS {
+        authority.add_program(Pubkey::new_unique()).unwrap();
+    }
+
+    assert_eq!(
+        authority.add_program(Pubkey::new_unique()).unwrap_err(),
+        VaultError::TooManyAuthorizedPrograms.into()
+    );
+}
+
+#[test]
+fn legacy_authority_parses_from_baseline_bytes() {
+    let perp = Pubkey::new_unique();
+    let perp_v2 = Pubkey::new_unique();
+
+    // What the baseline program wrote at `["vault_authority"]`, padded to its space
+    let mut data = VaultAuthority::DISCRIMINATOR.to_vec();
+    data.extend_from_slice(&2u32.to_le_bytes());
+    data.extend_from_slice(perp.as_ref());
+    data.extend_from_slice(perp_v2.as_ref());
+    data.push(253); // bump
+    data.resize(VaultAuthority::LEGACY_LEN, 0);
+    assert_eq!(data.len(), 525);
+
+    let legacy = LegacyVaultAuthority::try_from_account_data(&data).unwrap();
+    assert_eq!(legacy.authorized_programs, vec![perp, perp_v2]);
+    assert_eq!(legacy.bump, 253);
+
+    // A migrated whitelist is not legacy
+    let mut authority = fresh_authority(Pubkey::new_unique());
+    authority.authorized_programs = legacy.authorized_programs;
+    let mut current_data = vec![];
+    authority.try_serialize(&mut current_data).unwrap();
+    current_data.resize(VaultAuthority::LEN, 0);
+    assert_eq!(
+        LegacyVaultAuthority::try_from_account_data(&current_data).unwrap_err(),
+        VaultError::InvalidVaultAuthorityAccount.into()
+    );
+    let migrated = VaultAuthority::try_deserialize(&mut &current_data[..]).unwrap();
+    assert!(migrated.is_authorized(&perp_v2));
+}
+
+#[test]
+fn lock_record_len_matches_layout() {
+    let record = LockRecord {
+        vault: Pubkey::new_unique(),
+        caller_program: Pubkey::new_unique(),
+        position_id: 7,
+        amount: 100,
+        rent_payer: Pubkey::new_unique(),
+        created_at: 0,
+        expires_at: 0,
+        bump: 255,
+        epoch: 0,
+    };
+
+    let bytes = anchor_lang::prelude::borsh::to_vec(&record).unwrap();
+    assert_eq!(LockRecord::LEN, 8 + bytes.len());
+}
+
+#[test]
+fn insurance_fund_len_matches_layout() {
+    let fund = InsuranceFund {
+        mint: Pubkey::new_unique(),
+        token_account: Pubkey::new_unique(),
+        balance: 1,
+        total_received: 2,
+        total_drawn: 3,
+        created_at: 0,
+        bump: 255,
+    };
+
+    let bytes = anchor_lang::prelude::borsh::to_vec(&fund).unwrap();
+    assert_eq!(InsuranceFund::LEN, 8 + bytes.len());
+}
+
+fn token_2022_mint_data(extensions: &[ExtensionType]) -> Vec<u8> {
+    use anchor_spl::token_2022::spl_token_2022::extension::{
+        permanent_delegate::PermanentDelegate, transfer_fee::TransferFeeConfig,
+        BaseStateWithExtensionsMut, StateWithExtensionsMut,
+    };
+
+    let len = ExtensionType::try_calculate_account_len::<MintState>(extensions).unwrap();
+    let mut data = vec![0u8; len];
+    let mut state = StateWithExtensionsMut::<MintState>::unpack_uninitialized(&mut data).unwrap();
+
+    for extension in extensions {
+        match extension {
+            ExtensionType::PermanentDelegate => {
+                state.init_extension::<PermanentDelegate>(true).unwrap();
+            }
+            ExtensionType::TransferFeeConfig => {
+                state.init_extension::<TransferFeeConfig>(true).unwrap();
+            }
+            other => panic!("unsupported test extension {:?}", other),
+        }
+    }
+
+    state.base = MintState {
+        decimals: 6,
+        is_initialized: true,
+        ..Default::default()
+    };
+    state.pack_base();
+    state.init_account_type().unwrap();
+
+    data
+}
+
+fn check_mint(owner: &Pubkey, data: &mut [u8]) -> Result<()> {
+    let key = Pubkey::new_unique();
+    let mut lamports = 0;
+    let info = AccountInfo::new(&key, false, false, &mut lamports, data, owner, false, 0);
+    ensure_supported_mint(&info)
+}
+
+#[test]
+fn transfer_fee_mints_are_supported() {
+    let mut data = token_2022_mint_data(&[ExtensionType::TransferFeeConfig]);
+    assert!(check_mint(&token_2022::ID, &mut data).is_ok());
+}
+
+#[test]
+fn permanent_delegate_mints_are_rejected() {
+    let mut data = token_2022_mint_data(&[
+        ExtensionType::TransferFeeConfig,
+        ExtensionType::PermanentDelegate,
+    ]);
+
+    assert_eq!(
+        check_mint(&token_2022::ID, &mut data).unwrap_err(),
+        VaultError::UnsupportedMintExtension.into()
+    );
+}
+
+#[test]
+fn legacy_token_mints_skip_extension_check() {
+    let mut data = vec![0u8; 82];
+    assert!(check_mint(&anchor_spl::token::ID, &mut data).is_ok());
+}
+
+fn fresh_config() -> GlobalConfig {
+    GlobalConfig {
+        guardian: Pubkey::new_unique(),
+        paused: 0,
+        withdrawal_cooldown: 0,
+        max_deposit_per_tx: 0,
+        max_vault_balance: 0,
+        max_tvl: 0,
+        emergency_mode: false,
+        emergency_inactivity_period: 0,
+        last_authorized_activity: 0,
+        bump: 0,
+    }
+}
+
+#[test]
+fn pause_flags_are_independent() {
+    let mut config = fresh_config();
+
+    config.paused |= GlobalConfig::PAUSE_WITHDRAW | GlobalConfig::PAUSE_LOCK;
+
+    assert!(config.is_paused(GlobalConfig::PAUSE_WITHDRAW));
+    assert!(config.is_paused(GlobalConfig::PAUSE_LOCK));
+    assert!(!config.is_paused(GlobalConfig::PAUSE_DEPOSIT));
+    assert!(!config.is_paused(GlobalConfig::PAUSE_UNLOCK));
+
+    config.paused &= !GlobalConfig::PAUSE_LOCK;
+    assert!(!config.is_paused(GlobalConfig::PAUSE_LOCK));
+    assert!(config.is_paused(GlobalConfig::PAUSE_WITHDRAW));
+
+    config.paused = GlobalConfig::PAUSE_ALL;
+    assert!(config.is_paused(GlobalConfig::PAUSE_SUB_ACCOUNT));
+    assert!(config.is_paused(GlobalConfig::PAUSE_SETTINGS));
+}
+
+#[test]
+fn vault_len_matches_layout() {
+    let vault = fresh_vault(Pubkey::new_unique());
+    let bytes = anchor_lang::prelude::borsh::to_vec(&vault).unwrap();
+
+    assert_eq!(CollateralVault::LEN, 8 + bytes.len());
+}
+
+#[test]
+fn legacy_vault_parses_baseline_layout() {
+    let owner = Pubkey::new_unique();
+    let token_account = Pubkey::new_unique();
+
+    // Byte for byte what the baseline program wrote at `["vault", owner]`
+    let mut data = CollateralVault::DISCRIMINATOR.to_vec();
+    data.extend_from_slice(owner.as_ref());
+    data.extend_from_slice(token_account.as_ref());
+    data.extend_from_slice(&500u64.to_le_bytes()); // total_balance
+    data.extend_from_slice(&200u64.to_le_bytes()); // locked_balance
+    data.extend_from_slice(&300u64.to_le_bytes()); // available_balance
+    data.extend_from_slice(&900u64.to_le_bytes()); // total_deposited
+    data.extend_from_slice(&400u64.to_le_bytes()); // total_withdrawn
+    data.extend_from_slice(&1_700_000_000i64.to_le_bytes()); // created_at
+    data.push(254); // bump
+    assert_eq!(data.len(), 121);
+    assert_eq!(LegacyCollateralVault::LEN, 121);
+
+    let legacy = LegacyCollateralVault::try_from_account_data(&data).unwrap();
+    assert_eq!(legacy.owner, owner);
+    assert_eq!(legacy.token_account, token_account);
+    assert_eq!(legacy.total_balance, 500);
+    assert_eq!(legacy.locked_balance, 200);
+    assert_eq!(legacy.available_balance, 300);
+    assert_eq!(legacy.total_deposited, 900);
+    assert_eq!(legacy.total_withdrawn, 400);
+    assert_eq!(legacy.created_at, 1_700_000_000);
+    assert_eq!(legacy.bump, 254);
+
+    // Not a legacy vault: wrong size (a current vault) or wrong discriminator
+    let current = fresh_vault(owner);
+    let mut current_data = CollateralVault::DISCRIMINATOR.to_vec();
+    current_data.extend(anchor_lang::prelude::borsh::to_vec(&current).unwrap());
+    assert_eq!(
+        LegacyCollateralVault::try_from_account_data(&current_data).unwrap_err(),
+        VaultError::InvalidVaultAccount.into()
+    );
+    data[0] ^= 1;
+    assert_eq!(
+        LegacyCollateralVault::try_from_account_data(&data).unwrap_err(),
+        VaultError::InvalidVaultAccount.into()
+    );
+}
+
+#[test]
+fn legacy_balances_carry_over_net_of_fees() {
+    let legacy = LegacyCollateralVault {
+        owner: Pubkey::new_unique(),
+        token_account: Pubkey::new_unique(),
+        total_balance: 500,
+        locked_balance: 200,
+        available_balance: 300,
+        total_deposited: 500,
+        total_withdrawn: 0,
+        created_at: 0,
+        bump: 255,
+    };
+
+    // Everything arrived (plus untracked dust): ledger unchanged
+    assert_eq!(legacy.carried_balances(500).unwrap(), (300, 200));
+    assert_eq!(legacy.carried_balances(510).unwrap(), (300, 200));
+
+    // Fee shortfall comes out of available, then locked
+    assert_eq!(legacy.carried_balances(490).unwrap(), (290, 200));
+    assert_eq!(legacy.carried_balances(150).unwrap(), (0, 150));
+    assert_eq!(legacy.carried_balances(0).unwrap(), (0, 0));
+}
+
+
+#[test]
+fn pro_rata_shares_sum_to_total() {
+    let shares = pro_rata_shares(100, &[50, 50, 50]).unwrap();
+    assert_eq!(shares, vec![34, 33, 33]);
+
+    let shares = pro_rata_shares(10, &[500, 300, 200]).unwrap();
+    assert_eq!(shares, vec![5, 3, 2]);
+
+    let shares = pro_rata_shares(7, &[0, 3, 4]).unwrap();
+    assert_eq!(shares, vec![0, 3, 4]);
+}
+
+#[test]
+fn pro_rata_shares_reject_underfunded_set() {
+    assert_eq!(
+        pro_rata_shares(8, &[3, 4]).unwrap_err(),
+        VaultError::InsufficientAvailableBalance.into()
+    );
+    assert_eq!(
+        pro_rata_shares(1, &[]).unwrap_err(),
+        VaultError::InsufficientAvailableBalance.into()
+    );
+}
+
+#[test]
+fn deposit_caps_are_enforced_separately() {
+    let mut config = fresh_config();
+
+    // Zero means unlimited
+    assert!(config.check_deposit_caps(u64::MAX, u64::MAX, u64::MAX).is_ok());
+
+    config.max_deposit_per_tx = 100;
+    config.max_vault_balance = 1_000;
+    config.max_tvl = 10_000;
+
+    assert!(config.check_deposit_caps(100, 1_000, 10_000).is_ok());
+    assert_eq!(
+        config.check_deposit_caps(101, 500, 500).unwrap_err(),
+        VaultError::DepositTooLarge.into()
+    );
+    assert_eq!(
+        config.check_deposit_caps(50, 1_001, 5_000).unwrap_err(),
+        VaultError::VaultCapExceeded.into()
+    );
+    assert_eq!(
+        config.check_deposit_caps(50, 500, 10_001).unwrap_err(),
+        VaultError::TvlCapExceeded.into()
+    );
+}
+
+#[test]
+fn withdrawal_limit_rolls_over_the_window() {
+    let mut vault = fresh_vault(Pubkey::new_unique());
+    assert_eq!(vault.set_withdrawal_limit(100, 3_600, 1_000).unwrap(), 1_000);
+
+    vault.record_outflow(60, 1_000).unwrap();
+    vault.record_outflow(40, 2_000).unwrap();
+    assert_eq!(
+        vault.record_outflow(1, 4_599).unwrap_err(),
+        VaultError::WithdrawalLimitExceeded.into()
+    );
+
+    // The window rolls rather than resets: the boundary opens no burst
+    assert_eq!(
+        vault.record_outflow(1, 4_600).unwrap_err(),
+        VaultError::WithdrawalLimitExceeded.into()
+    );
+
+    // Halfway into the next window, half of the previous outflow still counts
+    assert_eq!(
+        vault.record_outflow(51, 6_400).unwrap_err(),
+        VaultError::WithdrawalLimitExceeded.into()
+    );
+    vault.record_outflow(50, 6_400).unwrap();
+
+    // Two windows on, nothing is carried
+    vault.record_outflow(100, 11_800).unwrap();
+}
+
+#[test]
+fn withdrawal_limit_raise_is_delayed() {
+    let mut vault = fresh_vault(Pubkey::new_unique());
+    vault.set_withdrawal_limit(100, 3_600, 0).unwrap();
+
+    let effective_at = vault.set_withdrawal_limit(1_000, 3_600, 10).unwrap();
+    assert_eq!(effective_at, 10 + CollateralVault::WITHDRAWAL_LIMIT_RAISE_DELAY);
+    assert_eq!(
+        vault.record_outflow(101, 20).unwrap_err(),
+        VaultError::WithdrawalLimitExceeded.into()
+    );
+
+    vault.record_outflow(1_000, effective_at).unwrap();
+    assert_eq!(vault.withdrawal_limit, 1_000);
+
+    // Removing the limit is a raise; a later tightening cancels it
+    vault.set_withdrawal_limit(0, 0, effective_at).unwrap();
+    vault.set_withdrawal_limit(50, 3_600, effective_at + 1).unwrap();
+    assert_eq!(vault.withdrawal_limit, 50);
+    assert_eq!(vault.pending_limit_effective_at, 0);
+}
+
+#[test]
+fn lock_expiry_is_opt_in() {
+    let mut record = LockRecord {
+        vault: Pubkey::new_unique(),
+        caller_program: Pubkey::new_unique(),
+        position_id: 1,
+        amount: 10,
+        rent_payer: Pubkey::new_unique(),
+        created_at: 0,
+        expires_at: 0,
+        bump: 255,
+        epoch: 0,
+    };
+    assert!(!record.is_expired(i64::MAX));
+
+    record.expires_at = 100;
+    assert!(!record.is_expired(99));
+    assert!(record.is_expired(100));
+}
+
+#[test]
+fn emergency_mode_triggers_on_flag_or_inactivity() {
+    let mut config = fresh_config();
+    config.last_authorized_activity = 1_000;
+    assert!(!config.is_emergency(i64::MAX));
+
+    config.emergency_inactivity_period = 500;
+    assert!(!config.is_emergency(1_499));
+    assert!(config.is_emergency(1_500));
+
+    config.record_authorized_activity(1_500);
+    assert!(!config.is_emergency(1_500));
+
+    config.emergency_mode = true;
+    assert!(config.is_emergency(1_500));
+}
+
+#[test]
+fn allowlist_entries_activate_after_delay() {
+    let mut allowlist = WithdrawAllowlist {
+        vault: Pubkey::new_unique(),
+        entries: Vec::new(),
+        bump: 255,
+    };
+    let cold_wallet = Pubkey::new_unique();
+
+    let active_at = allowlist.add_destination(cold_wallet, 1_000).unwrap();
+    assert_eq!(active_at, 1_000 + WithdrawAllowlist::ACTIVATION_DELAY);
+    assert!(!allowlist.is_allowed(&cold_wallet, active_at - 1));
+    assert!(allowlist.is_allowed(&cold_wallet, active_at));
+    assert!(!allowlist.is_allowed(&Pubkey::new_unique(), active_at));
+
+    assert_eq!(
+        allowlist.add_destination(cold_wallet, 2_000).unwrap_err(),
+        VaultError::DestinationAlreadyAllowed.into()
+    );
+
+    allowlist.remove_destination(&cold_wallet).unwrap();
+    assert!(!allowlist.is_allowed(&cold_wallet, active_at));
+}
+
+#[test]
+fn allowlist_is_capped() {
+    let mut allowlist = WithdrawAllowlist {
+        vault: Pubkey::new_unique(),
+        entries: Vec::new(),
+        bump: 255,
+    };
+
+    for _ in 0..WithdrawAllowlist::MAX_ENTRIES {
+        allowlist.add_destination(Pubkey::new_unique(), 0).unwrap();
+    }
+
+    assert_eq!(
+        allowlist.add_destination(Pubkey::new_unique(), 0).unwrap_err(),
+        VaultError::TooManyDestinations.into()
+    );
+
+    let full = WithdrawAllowlist {
+        vault: allowlist.vault,
+        entries: allowlist.entries.clone(),
+        bump: 255,
+    };
+    let bytes = anchor_lang::prelude::borsh::to_vec(&full).unwrap();
+    assert_eq!(WithdrawAllowlist::LEN, 8 + bytes.len());
+}
+
+#[test]
+fn delegates_only_pay_allowlisted_destinations() {
+    let vault = fresh_vault(Pubkey::new_unique());
+    let mut allowlist = WithdrawAllowlist {
+        vault: Pubkey::new_unique(),
+        entries: Vec::new(),
+        bump: 255,
+    };
+    let cold_wallet = Pubkey::new_unique();
+    let stranger = Pubkey::new_unique();
+    let active_at = allowlist.add_destination(cold_wallet, 0).unwrap();
+
+    // Without an allowlist the owner may pay anyone, a delegate no one
+    vault.check_withdraw_destination(None, &stranger, false, active_at).unwrap();
+    assert_eq!(
+        vault.check_withdraw_destination(None, &cold_wallet, true, active_at).unwrap_err(),
+        VaultError::AllowlistRequired.into()
+    );
+
+    vault
+        .check_withdraw_destination(Some(&allowlist), &cold_wallet, true, active_at)
+        .unwrap();
+    assert_eq!(
+        vault
+            .check_withdraw_destination(Some(&allowlist), &cold_wallet, true, active_at - 1)
+            .unwrap_err(),
+        VaultError::DestinationNotAllowed.into()
+    );
+    assert_eq!(
+        vault
+            .check_withdraw_destination(Some(&allowlist), &stranger, true, active_at)
+            .unwrap_err(),
+        VaultError::DestinationNotAllowed.into()
+    );
+}
+
+#[test]
+fn delegate_permissions_cap_and_expiry() {
+    let mut delegate = Delegate {
+        vault: Pubkey::new_unique(),
+        delegate: Pubkey::new_unique(),
+        permissions: Delegate::PERMISSION_WITHDRAW,
+        withdraw_cap: 100,
+        withdrawn: 0,
+        expires_at: 1_000,
+        bump: 255,
+    };
+
+    assert!(delegate.authorize(Delegate::PERMISSION_WITHDRAW, 999).is_ok());
+    assert_eq!(
+        delegate.authorize(Delegate::PERMISSION_DEPOSIT, 999).unwrap_err(),
+        VaultError::DelegatePermissionDenied.into()
+    );
+    assert_eq!(
+        delegate.authorize(Delegate::PERMISSION_WITHDRAW, 1_000).unwrap_err(),
+        VaultError::DelegateExpired.into()
+    );
+
+    delegate.record_withdrawal(60).unwrap();
+    delegate.record_withdrawal(40).unwrap();
+    assert_eq!(delegate.record_withdrawal(1).unwrap_err(), VaultError::DelegateCapExceeded.into());
+
+    let bytes = anchor_lang::prelude::borsh::to_vec(&delegate).unwrap();
+    assert_eq!(Delegate::LEN, 8 + bytes.len());
+}
+
+#[test]
+fn guardian_cosign_above_threshold() {
+    let mut vault = fresh_vault(Pubkey::new_unique());
+    let guardian = Pubkey::new_unique();
+
+    // No guardian: nothing to co-sign
+    vault.require_guardian_cosign(u64::MAX, None, 0).unwrap();
+
+    // First guardian applies immediately
+    assert_eq!(vault.set_guardian(guardian, 1_000, 10).unwrap(), 10);
+
+    vault.require_guardian_cosign(1_000, None, 10).unwrap();
+    assert_eq!(
+        vault.require_guardian_cosign(1_001, None, 10).unwrap_err(),
+        VaultError::GuardianSignatureRequired.into()
+    );
+    assert_eq!(
+        vault.require_guardian_cosign(1_001, Some(Pubkey::new_unique()), 10).unwrap_err(),
+        VaultError::GuardianSignatureRequired.into()
+    );
+    vault.require_guardian_cosign(1_001, Some(guardian), 10).unwrap();
+}
+
+#[test]
+fn guardian_cosign_counts_split_withdrawals() {
+    let mut vault = fresh_vault(Pubkey::new_unique());
+    let guardian = Pubkey::new_unique();
+    vault.set_guardian(guardian, 1_000, 0).unwrap();
+
+    // Two halves of an over-threshold withdrawal in one window
+    vault.require_guardian_cosign(600, None, 10).unwrap();
+    vault.record_outflow(600, 10).unwrap();
+    assert_eq!(
+        vault.require_guardian_cosign(401, None, 20).unwrap_err(),
+        VaultError::GuardianSignatureRequired.into()
+    );
+    vault.require_guardian_cosign(400, None, 20).unwrap();
+    vault.require_guardian_cosign(401, Some(guardian), 20).unwrap();
+
+    // Without a withdrawal limit the window is a day, and it rolls
+    let day = CollateralVault::DEFAULT_OUTFLOW_WINDOW;
+    assert_eq!(
+        vault.require_guardian_cosign(401, None, 10 + day).unwrap_err(),
+        VaultError::GuardianSignatureRequired.into()
+    );
+    assert_eq!(
+        vault.require_guardian_cosign(701, None, 10 + day + day / 2).unwrap_err(),
+        VaultError::GuardianSignatureRequired.into()
+    );
+    vault.require_guardian_cosign(700, None, 10 + day + day / 2).unwrap();
+
+    // A limit's own window applies to the threshold too
+    let later = 10 + 3 * day;
+    vault.set_withdrawal_limit(5_000, 100, later).unwrap();
+    vault.record_outflow(900, later).unwrap();
+    assert_eq!(
+        vault.require_guardian_cosign(101, None, later + 100).unwrap_err(),
+        VaultError::GuardianSignatureRequired.into()
+    );
+    vault.require_guardian_cosign(1_000, None, later + 200).unwrap();
+}
+
+#[test]
+fn cancelled_request_refunds_its_outflow() {
+    let mut vault = fresh_vault(Pubkey::new_unique());
+    vault.set_withdrawal_limit(1_000, 3_600, 0).unwrap();
+    vault.set_guardian(Pubkey::new_unique(), 1_000, 0).unwrap();
+
+    // request → cancel → withdraw, all in one window
+    vault.require_guardian_cosign(600, None, 10).unwrap();
+    vault.record_pending_outflow(600, 10).unwrap();
+    vault.record_outflow(300, 20).unwrap();
+    vault.refund_pending_outflow(30).unwrap();
+    assert_eq!(vault.withdrawn_in_window, 300);
+    assert_eq!(vault.pending_in_window, 0);
+
+    vault.available_balance = 700;
+    vault.total_balance = 700;
+    vault.check_instant_withdrawal(700, None, 40).unwrap();
+    assert_eq!(vault.withdrawn_in_window, 1_000);
+
+    // A request charged in the previous window is refunded from what that
+    // window still carries; the new window's outflow is left alone
+    vault.record_pending_outflow(200, 10 + 7_200).unwrap();
+    vault.record_outflow(800, 10 + 10_800).unwrap();
+    vault.refund_pending_outflow(20 + 10_800).unwrap();
+    assert_eq!(vault.withdrawn_in_window, 800);
+    assert_eq!(vault.withdrawn_in_prev_window, 0);
+    vault.record_outflow(200, 20 + 10_800).unwrap();
+}
+
+#[test]
+fn guardian_rotation_is_delayed() {
+    let mut vault = fresh_vault(Pubkey::new_unique());
+    let guardian = Pubkey::new_unique();
+    let next_guardian = Pubkey::new_unique();
+    vault.set_guardian(guardian, 1_000, 0).unwrap();
+
+    // Lowering the threshold with the same guardian is immediate
+    assert_eq!(vault.set_guardian(guardian, 500, 5).unwrap(), 5);
+    assert_eq!(vault.cosign_threshold, 500);
+
+    let effective_at = vault.set_guardian(next_guardian, 500, 10).unwrap();
+    assert_eq!(effective_at, 10 + CollateralVault::GUARDIAN_ROTATION_DELAY);
+    vault.require_guardian_cosign(501, Some(guardian), effective_at - 1).unwrap();
+
+    assert_eq!(
+        vault.require_guardian_cosign(501, Some(guardian), effective_at).unwrap_err(),
+        VaultError::GuardianSignatureRequired.into()
+    );
+    assert_eq!(vault.guardian, next_guardian);
+}
+
+#[test]
+fn sub_account_len_matches_layout() {
+    let sub_account = SubAccount {
+        vault: Pubkey::new_unique(),
+        index: 3,
+        total_balance: 1,
+        locked_balance: 2,
+        available_balance: 3,
+        created_at: 0,
+        bump: 255,
+        epoch: 0,
+        lock_count: 0,
+    };
+
+    let bytes = anchor_lang::prelude::borsh::to_vec(&sub_account).unwrap();
+    assert_eq!(SubAccount::LEN, 8 + bytes.len());
+}
+
+/// Mock Pyth price account with the fields `oracle::parse_pyth_price` reads.
+fn mock_pyth_price_data(
+    price: i64,
+    conf: u64,
+    expo: i32,
+    publish_time: i64,
+    status: u32,
+) -> Vec<u8> {
+    let mut data = vec![0u8; 3312];
+    data[0..4].copy_from_slice(&0xa1b2_c3d4u32.to_le_bytes());
+    data[4..8].copy_from_slice(&2u32.to_le_bytes());
+    data[8..12].copy_from_slice(&3u32.to_le_bytes());
+    data[20..24].copy_from_slice(&expo.to_le_bytes());
+    data[96..104].copy_from_slice(&publish_time.to_le_bytes());
+    data[208..216].copy_from_slice(&price.to_le_bytes());
+    data[216..224].copy_from_slice(&conf.to_le_bytes());
+    data[224..228].copy_from_slice(&status.to_le_bytes());
+    data
+}
+
+fn load_mock_oracle(data: &mut [u8]) -> Result<oracle::OraclePrice> {
+    let key = Pubkey::new_unique();
+    let owner = Pubkey::new_unique();
+    let mut lamports = 0;
+    let info = AccountInfo::new(&key, false, false, &mut lamports, data, &owner, false, 0);
+    oracle::load_oracle_price(&info)
+}
+
+#[test]
+fn mock_oracle_price_is_parsed() {
+    // SOL at $150.12345678
+    let mut data = mock_pyth_price_data(15_012_345_678, 5_000, -8, 1_700_000_000, 1);
+    let price = load_mock_oracle(&mut data).unwrap();
+
+    assert_eq!(price.price, 15_012_345_678);
+    assert_eq!(price.conf, 5_000);
+    assert_eq!(price.expo, -8);
+    assert_eq!(price.publish_time, 1_700_000_000);
+}
+
+#[test]
+fn invalid_oracle_accounts_are_rejected() {
+    let halted = 2;
+    let mut data = mock_pyth_price_data(100, 0, -8, 0, halted);
+    assert_eq!(load_mock_oracle(&mut data).unwrap_err(), VaultError::InvalidOracle.into());
+
+    let mut data = mock_pyth_price_data(-1, 0, -8, 0, 1);
+    assert_eq!(load_mock_oracle(&mut data).unwrap_err(), VaultError::InvalidOracle.into());
+
+    let mut data = mock_pyth_price_data(100, 0, -8, 0, 1);
+    data[0] ^= 0xff;
+    assert_eq!(load_mock_oracle(&mut data).unwrap_err(), VaultError::InvalidOracle.into());
+
+    let mut data = mock_pyth_price_data(100, 0, -8, 0, 1);
+    assert_eq!(load_mock_oracle(&mut data[..100]).unwrap_err(), VaultError::InvalidOracle.into());
+}
+
+#[test]
+fn collateral_value_applies_decimals_and_haircut() {
+    let mut data = mock_pyth_price_data(15_000_000_000, 0, -8, 0, 1);
+    let sol = load_mock_oracle(&mut data).unwrap();
+
+    // 2 SOL (9 decimals) at $150 = $300.000000
+    assert_eq!(oracle::value_collateral(2_000_000_000, 9, &sol, 0).unwrap(), 300_000_000);
+    // 20% haircut
+    assert_eq!(oracle::value_collateral(2_000_000_000, 9, &sol, 2_000).unwrap(), 240_000_000);
+    // Full haircut
+    assert_eq!(oracle::value_collateral(2_000_000_000, 9, &sol, 10_000).unwrap(), 0);
+
+    // Positive scale: 1 unit of a 0-decimal token at $150
+    assert_eq!(oracle::value_collateral(1, 0, &sol, 0).unwrap(), 150_000_000);
+
+    assert_eq!(
+        oracle::value_collateral(1, 0, &sol, 10_001).unwrap_err(),
+        VaultError::InvalidCollateralConfig.into()
+    );
+}
+
+#[test]
+fn stale_oracle_prices_are_rejected() {
+    let config = CollateralConfig {
+        mint: Pubkey::new_unique(),
+        oracle: Pubkey::new_unique(),
+        haircut_bps: 0,
+        max_staleness: 60,
+        bump: 255,
+    };
+
+    assert!(config.check_fresh(1_000, 1_060).is_ok());
+    assert_eq!(config.check_fresh(1_000, 1_061).unwrap_err(), VaultError::StaleOraclePrice.into());
+
+    let bytes = anchor_lang::prelude::borsh::to_vec(&config).unwrap();
+    assert_eq!(CollateralConfig::LEN, 8 + bytes.len());
+}
+
+#[test]
+fn vault_state_snapshot_round_trips_and_sets_flags() {
+    let key = Pubkey::new_unique();
+    let mut vault = fresh_vault(Pubkey::new_unique());
+    vault.total_balance = 1_000;
+    vault.locked_balance = 300;
+    vault.available_balance = 500;
+    vault.pending_withdrawal = 200;
+    vault.withdrawal_claimable_at = 42;
+    vault.withdrawal_blocked = true;
+    vault.guardian = Pubkey::new_unique();
+
+    let snapshot = vault.snapshot(key);
+    assert_eq!(snapshot.snapshot_version, VaultStateSnapshot::VERSION);
+    assert_eq!(snapshot.vault, key);
+    assert_eq!(snapshot.pending_withdrawal, 200);
+    assert!(snapshot.has_flag(VaultStateSnapshot::FLAG_WITHDRAWAL_BLOCKED));
+    assert!(snapshot.has_flag(VaultStateSnapshot::FLAG_GUARDIAN_SET));
+    assert!(!snapshot.has_flag(VaultStateSnapshot::FLAG_ALLOWLIST_ENABLED));
+    assert!(!snapshot.has_flag(VaultStateSnapshot::FLAG_BAD_DEBT));
+
+    // Later versions append fields; a v1 reader still decodes the prefix
+    let mut bytes = anchor_lang::prelude::borsh::to_vec(&snapshot).unwrap();
+    assert_eq!(VaultStateSnapshot::decode(&bytes).unwrap(), snapshot);
+    bytes.extend_from_slice(&[7; 16]);
+    assert_eq!(VaultStateSnapshot::decode(&bytes).unwrap(), snapshot);
+
+    bytes[0] = 0;
+    assert_eq!(
+        VaultStateSnapshot::decode(&bytes).unwrap_err(),
+        VaultError::InvalidVaultStateData.into()
+    );
+    assert_eq!(
+        VaultStateSnapshot::decode(&bytes[..10]).unwrap_err(),
+        VaultError::InvalidVaultStateData.into()
+    );
+}
+
+fn fresh_tvl(mint: Pubkey) -> TvlCounter {
+    TvlCounter {
+        mint,
+        total_value_locked: 0,
+        bump: 0,
+        reconciled: false,
+    }
+}
+
+#[test]
+fn third_party_deposit_credits_owner_net_of_fees() {
+    // `deposit_for` books exactly like `deposit`: the funder never shows up
+    // on the ledger, the owner's vault gets what arrived
+    let owner = Pubkey::new_unique();
+    let mut vault = fresh_vault(owner);
+    let mut tvl = fresh_tvl(vault.mint);
+    let mut config = fresh_config();
+
+    vault.credit_deposit(&mut tvl, &config, 1_000, 990).unwrap();
+    assert_eq!(vault.owner, owner);
+    assert_eq!(vault.total_balance, 990);
+    assert_eq!(vault.available_balance, 990);
+    assert_eq!(vault.total_deposited, 990);
+    assert_eq!(tvl.total_value_locked, 990);
+
+    assert_eq!(
+        vault.credit_deposit(&mut tvl, &config, 1, 0).unwrap_err(),
+        VaultError::InvalidAmount.into()
+    );
+
+    // A funder is held to the same caps as the owner
+    config.max_vault_balance = 1_500;
+    assert_eq!(
+        vault.credit_deposit(&mut tvl, &config, 600, 600).unwrap_err(),
+        VaultError::VaultCapExceeded.into()
+    );
+    config.max_deposit_per_tx = 400;
+    assert_eq!(
+        vault.credit_deposit(&mut tvl, &config, 401, 401).unwrap_err(),
+        VaultError::DepositTooLarge.into()
+    );
+    vault.credit_deposit(&mut tvl, &config, 400, 400).unwrap();
+    assert_eq!(vault.total_balance, 1_390);
+    assert_eq!(tvl.total_value_locked, 1_390);
+}
+
+#[test]
+fn instant_withdrawal_checks_then_debits() {
+    let mut vault = fresh_vault(Pubkey::new_unique());
+    let mut tvl = fresh_tvl(vault.mint);
+    let config = fresh_config();
+    vault.credit_deposit(&mut tvl, &config, 1_000, 1_000).unwrap();
+
+    vault.available_balance = 700;
+    vault.locked_balance = 300;
+
+    assert_eq!(
+        vault.check_instant_withdrawal(701, None, 0).unwrap_err(),
+        VaultError::InsufficientAvailableBalance.into()
+    );
+    vault.check_instant_withdrawal(700, None, 0).unwrap();
+
+    // Fee mints: the vault is debited what left it
+    vault.debit_withdrawal(&mut tvl, 700).unwrap();
+    assert_eq!(vault.total_balance, 300);
+    assert_eq!(vault.available_balance, 0);
+    assert_eq!(vault.total_withdrawn, 700);
+    assert_eq!(tvl.total_value_locked, 300);
+
+    assert_eq!(vault.debit_withdrawal(&mut tvl, 1).unwrap_err(), VaultError::MathOverflow.into());
+}
+
+#[test]
+fn vault_with_bad_debt_cannot_close() {
+    let mut vault = fresh_vault(Pubkey::new_unique());
+    vault.check_closable().unwrap();
+
+    vault.bad_debt = 1;
+    assert_eq!(vault.check_closable().unwrap_err(), VaultError::OutstandingBadDebt.into());
+
+    vault.bad_debt = 0;
+    vault.total_balance = 1;
+    assert_eq!(vault.check_closable().unwrap_err(), VaultError::VaultNotEmpty.into());
+}
+
+#[test]
+fn tvl_counter_errors_instead_of_clamping() {
+    let mut tvl = fresh_tvl(Pubkey::new_unique());
+    tvl.add(500).unwrap();
+    tvl.sub(200).unwrap();
+    assert_eq!(tvl.total_value_locked, 300);
+
+    // Drift surfaces as an error, the counter is left as it was
+    assert_eq!(tvl.sub(301).unwrap_err(), VaultError::MathOverflow.into());
+    assert_eq!(tvl.total_value_locked, 300);
+
+    tvl.total_value_locked = u64::MAX;
+    assert_eq!(tvl.add(1).unwrap_err(), VaultError::MathOverflow.into());
+}
+
+#[test]
+fn emergency_epoch_strands_old_records() {
+    let mut vault = fresh_vault(Pubkey::new_unique());
+    let record = LockRecord {
+        vault: Pubkey::new_unique(),
+        caller_program: Pubkey::new_unique(),
+        position_id: 1,
+        amount: 10,
+        rent_payer: Pubkey::new_unique(),
+        created_at: 0,
+        expires_at: 0,
+        bump: 255,
+        epoch: vault.epoch,
+    };
+    let sub_account = SubAccount {
+        vault: Pubkey::new_unique(),
+        index: 0,
+        total_balance: 10,
+        locked_balance: 10,
+        available_balance: 0,
+        created_at: 0,
+        bump: 255,
+        epoch: vault.epoch,
+        lock_count: 0,
+    };
+    vault.require_current_lock(&record).unwrap();
+    vault.require_current_sub_account(&sub_account).unwrap();
+
+    vault.start_new_epoch().unwrap();
+    assert_eq!(
+        vault.require_current_lock(&record).unwrap_err(),
+        VaultError::StaleLockRecord.into()
+    );
+    assert_eq!(
+        vault.require_current_sub_account(&sub_account).unwrap_err(),
+        VaultError::StaleSubAccount.into()
+    );
+
+    vault.epoch = u32::MAX;
+    assert_eq!(vault.start_new_epoch().unwrap_err(), VaultError::MathOverflow.into());
+}
+
+#[test]
+fn emergency_withdrawal_skips_limit_and_guardian() {
+    let now = 1_000;
+    let mut vault = fresh_vault(Pubkey::new_unique());
+    vault.total_balance = 1_000;
+    vault.available_balance = 400;
+    vault.locked_balance = 600;
+    vault.withdrawal_limit = 100;
+    vault.withdrawal_window = 3_600;
+    vault.guardian = Pubkey::new_unique();
+    vault.record_outflow(100, now).unwrap();
+
+    // The normal paths are shut: limit used up, guardian unavailable
+    assert_eq!(
+        vault.check_instant_withdrawal(1, None, now).unwrap_err(),
+        VaultError::GuardianSignatureRequired.into()
+    );
+    assert_eq!(
+        vault.record_outflow(1, now).unwrap_err(),
+        VaultError::WithdrawalLimitExceeded.into()
+    );
+
+    let epoch = vault.epoch;
+    vault.book_emergency_withdrawal(1_000).unwrap();
+    assert_eq!(vault.total_balance, 0);
+    assert_eq!(vault.available_balance, 0);
+    assert_eq!(vault.locked_balance, 0);
+    assert_eq!(vault.total_withdrawn, 1_000);
+    assert_eq!(vault.withdrawn_in_window, 100);
+    assert_eq!(vault.epoch, epoch + 1);
+
+    // A counter that missed earlier deposits floors at zero instead of reverting
+    let mut tvl = fresh_tvl(vault.mint);
+    tvl.add(300).unwrap();
+    tvl.sub_saturating(1_000);
+    assert_eq!(tvl.total_value_locked, 0);
+}
+
+#[test]
+fn vault_with_open_records_cannot_close() {
+    let mut vault = fresh_vault(Pubkey::new_unique());
+    vault.check_closable().unwrap();
+
+    // Each record type alone blocks the close: a recreated vault at the same
+    // PDA would otherwise inherit them
+    vault.lock_count = 1;
+    assert_eq!(vault.check_closable().unwrap_err(), VaultError::OpenVaultRecords.into());
+    vault.lock_count = 0;
+    vault.sub_account_count = 1;
+    assert_eq!(vault.check_closable().unwrap_err(), VaultError::OpenVaultRecords.into());
+    vault.sub_account_count = 0;
+    vault.delegate_count = 1;
+    assert_eq!(vault.check_closable().unwrap_err(), VaultError::OpenVaultRecords.into());
+    vault.delegate_count = 0;
+    vault.check_closable().unwrap();
+}
+
+fn fresh_lock(vault: Pubkey, amount: u64) -> LockRecord {
+    LockRecord {
+        vault,
+        caller_program: Pubkey::new_unique(),
+        position_id: 42,
+        amount,
+        rent_payer: Pubkey::new_unique(),
+        created_at: 0,
+        expires_at: 0,
+        bump: 255,
+        epoch: 0,
+    }
+}
+
+#[test]
+fn transfer_modes_draw_from_their_own_balance() {
+    let mut vault = fresh_vault(Pubkey::new_unique());
+    vault.total_balance = 1_000;
+    vault.available_balance = 600;
+    vault.locked_balance = 400;
+    let mut record = fresh_lock(Pubkey::new_unique(), 250);
+
+    // Available mode never touches locks, even when a record is passed
+    assert_eq!(
+        vault.debit_transfer(TransferMode::Available, 601, None, None).unwrap_err(),
+        VaultError::InsufficientAvailableBalance.into()
+    );
+    assert_eq!(
+        vault
+            .debit_transfer(TransferMode::Available, 100, Some(&mut record), None)
+            .unwrap(),
+        None
+    );
+    assert_eq!(vault.available_balance, 500);
+    assert_eq!(vault.locked_balance, 400);
+    assert_eq!(record.amount, 250);
+
+    // Locked mode settles one position and reports it
+    assert_eq!(
+        vault.debit_transfer(TransferMode::Locked, 100, None, None).unwrap_err(),
+        VaultError::LockRecordRequired.into()
+    );
+    assert_eq!(
+        vault.debit_transfer(TransferMode::Locked, 251, Some(&mut record), None).unwrap_err(),
+        VaultError::InsufficientLockedBalance.into()
+    );
+    assert_eq!(
+        vault
+            .debit_transfer(TransferMode::Locked, 150, Some(&mut record), None)
+            .unwrap(),
+        Some(42)
+    );
+    assert_eq!(record.amount, 100);
+    assert_eq!(vault.locked_balance, 250);
+    assert_eq!(vault.available_balance, 500);
+
+    // A lock from before an emergency withdrawal settles nothing
+    vault.start_new_epoch().unwrap();
+    assert_eq!(
+        vault.debit_transfer(TransferMode::Locked, 100, Some(&mut record), None).unwrap_err(),
+        VaultError::StaleLockRecord.into()
+    );
+    assert_eq!(record.amount, 100);
+}
+
+/// Token-2022 transfer fee as the token program charges it (rounded up).
+fn transfer_fee(
+    basis_points: u16,
+    maximum_fee: u64,
+) -> anchor_spl::token_2022::spl_token_2022::extension::transfer_fee::TransferFee {
+    anchor_spl::token_2022::spl_token_2022::extension::transfer_fee::TransferFee {
+        epoch: 0.into(),
+        maximum_fee: maximum_fee.into(),
+        transfer_fee_basis_points: basis_points.into(),
+    }
+}
+
+#[test]
+fn transfer_settles_what_the_tokens_did() {
+    let mut from = fresh_vault(Pubkey::new_unique());
+    let mut to = fresh_vault(Pubkey::new_unique());
+    let mut tvl = fresh_tvl(from.mint);
+    let config = fresh_config();
+    from.credit_deposit(&mut tvl, &config, 1_000, 1_000).unwrap();
+
+    // Plain mint: both ledgers move by the same amount, TVL is unchanged
+    from.debit_transfer(TransferMode::Available, 300, None, None).unwrap();
+    from.settle_transfer(&mut to, &mut tvl, 300, 300).unwrap();
+    assert_eq!((from.total_balance, from.available_balance), (700, 700));
+    assert_eq!((to.total_balance, to.available_balance), (300, 300));
+    assert_eq!(tvl.total_value_locked, 1_000);
+
+    // Fee mint: the sender loses the gross amount, the receiver gets the net,
+    // the withheld fee (1% of 333, rounded up) leaves TVL
+    let fee = transfer_fee(100, 1_000).calculate_fee(333).unwrap();
+    assert_eq!(fee, 4);
+    from.debit_transfer(TransferMode::Available, 333, None, None).unwrap();
+    from.settle_transfer(&mut to, &mut tvl, 333, 333 - fee).unwrap();
+    assert_eq!((from.total_balance, from.available_balance), (367, 367));
+    assert_eq!((to.total_balance, to.available_balance), (629, 629));
+    assert_eq!(tvl.total_value_locked, 996);
+    assert_eq!(from.total_balance + to.total_balance, tvl.total_value_locked);
+
+    // More arriving than was sent is not a transfer fee
+    assert_eq!(
+        from.settle_transfer(&mut to, &mut tvl, 10, 11).unwrap_err(),
+        VaultError::MathOverflow.into()
+    );
+}
+
+#[test]
+fn liquidation_seizes_gross_and_funds_net() {
+    let mut vault = fresh_vault(Pubkey::new_unique());
+    let mut tvl = fresh_tvl(vault.mint);
+    let config = fresh_config();
+    vault.credit_deposit(&mut tvl, &config, 2_000, 2_000).unwrap();
+    vault.available_balance = 800;
+    vault.locked_balance = 1_200;
+    let mut record = fresh_lock(Pubkey::new_unique(), 1_200);
+    let mut fund = InsuranceFund {
+        mint: vault.mint,
+        token_account: Pubkey::new_unique(),
+        balance: 0,
+        total_received: 0,
+        total_drawn: 0,
+        created_at: 0,
+        bump: 255,
+    };
+
+    // 0.5% fee mint: 199 * 0.5% = 0.995 rounds up to a whole token, so the
+    // fund nets 198 while the position pays the full reward plus penalty
+    let (liquidator_reward, insurance_fee) = (1_001, 199);
+    let fee = transfer_fee(50, u64::MAX);
+    assert_eq!(fee.calculate_fee(insurance_fee).unwrap(), 1);
+    assert_eq!(fee.calculate_fee(liquidator_reward).unwrap(), 6);
+
+    let seized = liquidator_reward + insurance_fee;
+    vault.seize_locked(&mut record, None, &mut tvl, seized).unwrap();
+    fund.credit(insurance_fee - fee.calculate_fee(insurance_fee).unwrap()).unwrap();
+
+    assert_eq!(record.amount, 0);
+    assert_eq!(vault.locked_balance, 0);
+    assert_eq!(vault.available_balance, 800);
+    assert_eq!(vault.total_balance, 800);
+    assert_eq!(tvl.total_value_locked, 800);
+    assert_eq!((fund.balance, fund.total_received), (198, 198));
+
+    // A position cannot lose more than it locked
+    let mut record = fresh_lock(Pubkey::new_unique(), 100);
+    vault.locked_balance = 100;
+    assert_eq!(
+        vault.seize_locked(&mut record, None, &mut tvl, 101).unwrap_err(),
+        VaultError::InsufficientLockedBalance.into()
+    );
+    assert_eq!((record.amount, vault.locked_balance), (100, 100));
+}
+
+fn fresh_sub_account(vault: Pubkey, total: u64, locked: u64) -> SubAccount {
+    SubAccount {
+        vault,
+        index: 1,
+        total_balance: total,
+        locked_balance: locked,
+        available_balance: total - locked,
+        created_at: 0,
+        bump: 255,
+        epoch: 0,
+        lock_count: 1,
+    }
+}
+
+#[test]
+fn sub_account_locks_settle_and_liquidate() {
+    let mut vault = fresh_vault(Pubkey::new_unique());
+    let mut tvl = fresh_tvl(vault.mint);
+    let config = fresh_config();
+    vault.credit_deposit(&mut tvl, &config, 1_000, 1_000).unwrap();
+
+    // 600 moved into the sub-account, 500 of it locked for position 42
+    vault.available_balance = 400;
+    vault.sub_account_balance = 600;
+    vault.lock_count = 1;
+    let mut sub_account = fresh_sub_account(Pubkey::new_unique(), 600, 500);
+    let mut record = fresh_lock(Pubkey::new_unique(), 500);
+
+    // Settling a losing position draws on the sub-account, not the vault pool
+    let position = vault
+        .debit_transfer(TransferMode::Locked, 200, Some(&mut record), Some(&mut sub_account))
+        .unwrap();
+    assert_eq!(position, Some(42));
+    assert_eq!(
+        (sub_account.total_balance, sub_account.locked_balance, sub_account.available_balance),
+        (400, 300, 100)
+    );
+    assert_eq!((record.amount, vault.sub_account_balance), (300, 400));
+    assert_eq!((vault.available_balance, vault.locked_balance), (400, 0));
+    let mut to = fresh_vault(Pubkey::new_unique());
+    vault.settle_transfer(&mut to, &mut tvl, 200, 200).unwrap();
+    assert_eq!(vault.total_balance, 800);
+
+    // Its available balance can be transferred too
+    assert_eq!(
+        vault
+            .debit_transfer(TransferMode::Available, 101, None, Some(&mut sub_account))
+            .unwrap_err(),
+        VaultError::InsufficientAvailableBalance.into()
+    );
+    vault.debit_transfer(TransferMode::Available, 100, None, Some(&mut sub_account)).unwrap();
+    vault.settle_transfer(&mut to, &mut tvl, 100, 100).unwrap();
+    assert_eq!(
+        (sub_account.total_balance, sub_account.available_balance, vault.sub_account_balance),
+        (300, 0, 300)
+    );
+
+    // Liquidation seizes the rest of the position from the sub-account
+    assert_eq!(
+        vault.seize_locked(&mut record, Some(&mut sub_account), &mut tvl, 301).unwrap_err(),
+        VaultError::InsufficientLockedBalance.into()
+    );
+    vault.seize_locked(&mut record, Some(&mut sub_account), &mut tvl, 300).unwrap();
+    assert_eq!((record.amount, sub_account.total_balance, sub_account.locked_balance), (0, 0, 0));
+    assert_eq!(
+        (vault.total_balance, vault.sub_account_balance, vault.available_balance),
+        (400, 0, 400)
+    );
+    assert_eq!(tvl.total_value_locked, 700);
+
+    vault.untrack_lock(Some(&mut sub_account)).unwrap();
+    assert_eq!((vault.lock_count, sub_account.lock_count), (0, 0));
+
+    // Sub-accounts from before an emergency withdrawal are out
+    vault.start_new_epoch().unwrap();
+    assert_eq!(
+        vault.debit_transfer(TransferMode::Available, 1, None, Some(&mut sub_account)).unwrap_err(),
+        VaultError::StaleSubAccount.into()
+    );
+}
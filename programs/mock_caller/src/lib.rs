@@ -0,0 +1,130 @@
+use anchor_lang::prelude::*;
+use collateral_vault::cpi::accounts::{LockCollateral, UnlockCollateral};
+use collateral_vault::program::CollateralVault;
+use collateral_vault::CALLER_AUTHORITY_SEED;
+
+declare_id!("4dE4eaA4cYTJ4PrDammP7Gr2vdVDpHFhTSEzQYg59iHF");
+
+/// Stand-in for an authorized integration (e.g. a position manager) in the
+/// TypeScript tests. Forwards lock and unlock to the vault, signed by its
+/// `CALLER_AUTHORITY_SEED` PDA the way a real caller program has to.
+#[program]
+pub mod mock_caller {
+    use super::*;
+
+    pub fn lock(
+        ctx: Context<ForwardLock>,
+        position_id: u64,
+        amount: u64,
+        expires_at: Option<i64>,
+    ) -> Result<()> {
+        let bump = [ctx.bumps.caller_authority];
+        let seeds: &[&[u8]] = &[CALLER_AUTHORITY_SEED, &bump];
+
+        collateral_vault::cpi::lock_collateral(
+            CpiContext::new_with_signer(
+                ctx.accounts.vault_program.to_account_info(),
+                LockCollateral {
+                    caller_program: ctx.accounts.caller_program.to_account_info(),
+                    caller_authority: ctx.accounts.caller_authority.to_account_info(),
+                    vault_authority: ctx.accounts.vault_authority.to_account_info(),
+                    global_config: ctx.accounts.global_config.to_account_info(),
+                    vault: ctx.accounts.vault.to_account_info(),
+                    lock_record: ctx.accounts.lock_record.to_account_info(),
+                    payer: ctx.accounts.payer.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                },
+                &[seeds],
+            ),
+            position_id,
+            amount,
+            expires_at,
+        )
+    }
+
+    pub fn unlock(ctx: Context<ForwardUnlock>, position_id: u64, amount: u64) -> Result<()> {
+        let bump = [ctx.bumps.caller_authority];
+        let seeds: &[&[u8]] = &[CALLER_AUTHORITY_SEED, &bump];
+
+        collateral_vault::cpi::unlock_collateral(
+            CpiContext::new_with_signer(
+                ctx.accounts.vault_program.to_account_info(),
+                UnlockCollateral {
+                    caller_program: ctx.accounts.caller_program.to_account_info(),
+                    caller_authority: ctx.accounts.caller_authority.to_account_info(),
+                    vault_authority: ctx.accounts.vault_authority.to_account_info(),
+                    global_config: ctx.accounts.global_config.to_account_info(),
+                    vault: ctx.accounts.vault.to_account_info(),
+                    lock_record: ctx.accounts.lock_record.to_account_info(),
+                    rent_receiver: ctx.accounts.rent_receiver.to_account_info(),
+                },
+                &[seeds],
+            ),
+            position_id,
+            amount,
+        )
+    }
+}
+
+#[derive(Accounts)]
+pub struct ForwardLock<'info> {
+    /// This program, passed on as the vault's `caller_program`
+    pub caller_program: Program<'info, crate::program::MockCaller>,
+
+    /// CHECK: PDA `[CALLER_AUTHORITY_SEED]`; signs the CPI
+    #[account(seeds = [CALLER_AUTHORITY_SEED], bump)]
+    pub caller_authority: UncheckedAccount<'info>,
+
+    /// CHECK: validated by the vault
+    pub vault_authority: UncheckedAccount<'info>,
+
+    /// CHECK: validated by the vault
+    #[account(mut)]
+    pub global_config: UncheckedAccount<'info>,
+
+    /// CHECK: validated by the vault
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: created or checked by the vault
+    #[account(mut)]
+    pub lock_record: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub vault_program: Program<'info, CollateralVault>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ForwardUnlock<'info> {
+    /// This program, passed on as the vault's `caller_program`
+    pub caller_program: Program<'info, crate::program::MockCaller>,
+
+    /// CHECK: PDA `[CALLER_AUTHORITY_SEED]`; signs the CPI
+    #[account(seeds = [CALLER_AUTHORITY_SEED], bump)]
+    pub caller_authority: UncheckedAccount<'info>,
+
+    /// CHECK: validated by the vault
+    pub vault_authority: UncheckedAccount<'info>,
+
+    /// CHECK: validated by the vault
+    #[account(mut)]
+    pub global_config: UncheckedAccount<'info>,
+
+    /// CHECK: validated by the vault
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: checked by the vault
+    #[account(mut)]
+    pub lock_record: UncheckedAccount<'info>,
+
+    /// CHECK: must be the lock record's rent payer; checked by the vault
+    #[account(mut)]
+    pub rent_receiver: UncheckedAccount<'info>,
+
+    pub vault_program: Program<'info, CollateralVault>,
+}